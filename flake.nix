@@ -37,7 +37,7 @@
           name = "ghcr.io/flakm/czujka-librus";
           tag = "latest";
 
-          contents = [ app pkgs.coreutils ];
+          contents = [ app pkgs.coreutils pkgs.tzdata ];
 
           config = {
             Cmd = [ "${app}/bin/librus-notifications" ];
@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::profile::StudentProfile;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Account {
+    /// Namespace for stored ids; empty for the legacy single-account setup.
+    pub id: String,
+    pub username: String,
+    pub password: String,
+    #[serde(rename = "student")]
+    pub profile: StudentProfile,
+}
+
+/// Reads the list of Librus accounts from the environment, or `None` when it defines none.
+///
+/// `LIBRUS_ACCOUNTS=emilka,jas` enables multi-account mode, where each entry reads
+/// `LIBRUS_<ID>_USERNAME`, `LIBRUS_<ID>_PASSWORD` and the `LIBRUS_<ID>_STUDENT_*` profile.
+/// Without it, the single `LIBRUS_USERNAME`/`LIBRUS_PASSWORD` pair and `STUDENT_*` are used.
+pub fn accounts_from_env(env: &dyn Fn(&str) -> Option<String>) -> Result<Option<Vec<Account>>> {
+    let Some(ids) = env("LIBRUS_ACCOUNTS") else {
+        let Some(username) = env("LIBRUS_USERNAME") else {
+            return Ok(None);
+        };
+        let Some(password) = env("LIBRUS_PASSWORD") else {
+            bail!("LIBRUS_PASSWORD must be set together with LIBRUS_USERNAME");
+        };
+        return Ok(Some(vec![Account {
+            id: String::new(),
+            username,
+            password,
+            profile: StudentProfile::from_env("", env),
+        }]));
+    };
+
+    let mut accounts: Vec<Account> = Vec::new();
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!(
+                "Invalid account id '{}' in LIBRUS_ACCOUNTS: use letters, digits and '_'",
+                id
+            );
+        }
+        if accounts.iter().any(|account| account.id == id) {
+            bail!("Duplicate account id '{}' in LIBRUS_ACCOUNTS", id);
+        }
+
+        let prefix = format!("LIBRUS_{}", id.to_uppercase());
+        let (Some(username), Some(password)) = (
+            env(&format!("{}_USERNAME", prefix)),
+            env(&format!("{}_PASSWORD", prefix)),
+        ) else {
+            bail!(
+                "{}_USERNAME and {}_PASSWORD must be set for account '{}'",
+                prefix,
+                prefix,
+                id
+            );
+        };
+
+        accounts.push(Account {
+            id: id.to_string(),
+            username,
+            password,
+            profile: StudentProfile::from_env(&format!("{}_", prefix), env),
+        });
+    }
+
+    if accounts.is_empty() {
+        bail!("LIBRUS_ACCOUNTS must list at least one account");
+    }
+    Ok(Some(accounts))
+}
@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::Local;
+
+use crate::anthropic::AnthropicClient;
+use crate::config::{LlmConfig, LlmProvider};
+use crate::models::{Analysis, AnnouncementItem, EventItem, GradeItem, HomeworkItem, MessageItem};
+use crate::openai::OpenAiClient;
+use crate::profile::StudentProfile;
+
+const ACTION_ITEMS_RULE: &str = "actionItems: każde konkretne działanie dla rodzica. kind: payment (wpłata), supplies (przybory), reading (czytanie, nauka), meeting (zebranie, konsultacje), test (sprawdzian, kartkówka). due tylko przy dokładnej dacie, amountPln tylko przy wpłatach, sourceId to Id elementu, z którego pochodzi działanie.";
+const ACTION_ITEMS_JSON: &str = r#""actionItems": [{"kind": "payment|supplies|reading|meeting|test", "due": "YYYY-MM-DD", "amountPln": 20.0, "description": "krótki opis", "sourceId": "Id"}]"#;
+
+/// A language model backend that turns school items into an [`Analysis`].
+///
+/// Backends only implement [`Analyzer::chat_json`]; the prompts live in the
+/// provided `summarize_*` methods so every provider gets the same instructions.
+#[async_trait]
+pub trait Analyzer: Send + Sync {
+    /// Human readable backend name used in logs.
+    fn name(&self) -> &str;
+
+    async fn chat_json(&self, system: &str, user: &str) -> Result<Analysis>;
+
+    async fn summarize_messages(
+        &self,
+        items: &[MessageItem],
+        profile: &StudentProfile,
+    ) -> Result<Analysis> {
+        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                format!(
+                    "{}. {}\n   Id: {}\n   Od: {}\n   Data: {}\n   Treść: {}",
+                    idx + 1,
+                    item.title,
+                    item.id,
+                    item.user,
+                    item.date,
+                    item.body
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!(
+            "DZISIAJ JEST: {}\n\nPrzeanalizuj wiadomości ze szkoły dla {}.\n\nKONTEKST: {}\n\nWIADOMOŚCI:\n{}\n\nIGNORUJ CAŁKOWICIE:\n- Wiadomości WYSŁANE PRZEZ RODZICA (np. \"Usprawiedliwienie\", prośby o zwolnienie) - to nasze wiadomości, nie od szkoły\n{}- Wiadomości od \"SuperAdministrator\"\n- Odpowiedzi \"Re:\" które tylko potwierdzają (np. \"Dziękuję za informację\")\n- WYDARZENIA KTÓRE JUŻ MINĘŁY (data w przeszłości względem dzisiaj)\n\nTYPY WIADOMOŚCI DO ROZPOZNANIA:\n- CZYTANIE: \"tekst do ładnego czytania\", strony z podręcznika\n- WYCIECZKA: koszty, godziny wyjazdu/powrotu, co zabrać\n- PRZYBORY: materiały na plastykę, mundurki\n- KONSULTACJE: terminy spotkań z nauczycielami\n- TESTY/SPRAWDZIANY: daty, zakres materiału\n- AKCJE: zbiórki, terminy, wpłaty\n\nPILNOŚĆ (względem dzisiejszej daty {}):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-14 dni\n- NIEPILNE: termin > 14 dni lub informacyjne\n\nWYODRĘBNIJ KONKRETNIE:\n💰 Kwoty i terminy wpłat\n🎒 Przybory do przyniesienia\n📖 Strony/materiał do nauki\n📅 Daty i godziny wydarzeń\n⏰ Godziny zbiórek\n\nFORMAT: Podsumowanie (2-3 zdania) to Twoja analiza CO TRZEBA ZROBIĆ.\nkeyPoints: max 5-7 najważniejszych, posortowane od najpilniejszych.\n{}\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co rodzic musi zrobić\", \"keyPoints\": [\"emoji **termin** - działanie\"], {}}}",
+            today,
+            profile.class_description(),
+            profile.context(),
+            items_text,
+            profile.ignore_rule(),
+            today,
+            ACTION_ITEMS_RULE,
+            ACTION_ITEMS_JSON
+        );
+
+        let system = format!("Jesteś asystentem rodzica {}. Wyodrębniasz KONKRETNE działania: co kupić, ile zapłacić, jakie strony przeczytać, kiedy przyjść. Ignorujesz wiadomości dla innych klas i wydarzenia które już minęły. Odpowiadasz TYLKO JSON.", profile.audience());
+
+        self.chat_json(&system, &prompt).await
+    }
+
+    async fn summarize_announcements(
+        &self,
+        items: &[AnnouncementItem],
+        profile: &StudentProfile,
+    ) -> Result<Analysis> {
+        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                format!(
+                    "{}. {}\n   Id: {}\n   Autor: {}\n   Data: {}\n   Treść: {}",
+                    idx + 1,
+                    item.title,
+                    item.id,
+                    item.author,
+                    item.date,
+                    item.content
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!(
+            "DZISIAJ JEST: {}\n\nPrzeanalizuj ogłoszenia ze szkoły dla {}.\n\nKONTEKST: {}\n\nOGŁOSZENIA:\n{}\n\nIGNORUJ CAŁKOWICIE:\n{}- Ogłoszenia od \"SuperAdministrator\" jeśli są ogólne\n- WYDARZENIA KTÓRE JUŻ MINĘŁY\n\nPILNOŚĆ (względem dzisiejszej daty):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-14 dni\n- NIEPILNE: termin > 14 dni lub informacyjne\n\nWYODRĘBNIJ KONKRETNIE:\n💰 Kwoty i terminy wpłat\n🎒 Przybory do przyniesienia\n📅 Daty i godziny wydarzeń\n⏰ Godziny zbiórek\n\nFORMAT: Podsumowanie (2-3 zdania). keyPoints: max 5-7 najważniejszych.\n{}\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co rodzic musi zrobić\", \"keyPoints\": [\"emoji **termin** - działanie\"], {}}}",
+            today,
+            profile.class_description(),
+            profile.context(),
+            items_text,
+            profile.ignore_rule(),
+            ACTION_ITEMS_RULE,
+            ACTION_ITEMS_JSON
+        );
+
+        let system = format!("Jesteś asystentem rodzica {}. Wyodrębniasz KONKRETNE działania. Ignorujesz ogłoszenia dla innych klas i minione wydarzenia. Odpowiadasz TYLKO JSON.", profile.audience());
+
+        self.chat_json(&system, &prompt).await
+    }
+
+    async fn summarize_grades(
+        &self,
+        items: &[GradeItem],
+        profile: &StudentProfile,
+    ) -> Result<Analysis> {
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                format!(
+                    "{}. Przedmiot: {}\n   Id: {}\n   Ocena: {}\n   Informacje: {}",
+                    idx + 1,
+                    item.subject,
+                    item.id,
+                    item.value,
+                    item.info
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!(
+            "Przeanalizuj nowe oceny ucznia.\n\nKONTEKST: {}\n\nOCENY:\n{}\n\nSYSTEM OCENIANIA:\n{}\n\nKATEGORIE OCEN: aktywność, sprawdzian, odpowiedź ustna, praca na lekcji, podsumowanie miesiąca\n\nZADANIA:\n1. Wyodrębnij kluczowe uwagi nauczycieli (np. \"mniej rozmawiać z koleżanką\", wyniki procentowe sprawdzianów)\n2. Określ pilność: PILNE tylko gdy są uwagi o problemach, NORMALNE dla pozytywnych ocen\n3. Napisz podsumowanie (2-3 zdania) w pozytywnym tonie podkreślając sukcesy\n4. Wypisz oceny: 📚 przedmiot | ⭐ **ocena** | 💬 istotna uwaga nauczyciela\n\nFORMAT: Podsumowanie to Twoja analiza - NIE cytuj dosłownie komentarzy nauczycieli.\n{}\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Twoja analiza postępów\", \"keyPoints\": [\"📚 Przedmiot | ⭐ **ocena** | 💬 uwaga\"], {}}}",
+            profile.context(),
+            items_text,
+            profile.grading_scale_text(),
+            ACTION_ITEMS_RULE,
+            ACTION_ITEMS_JSON
+        );
+
+        let system = format!("Jesteś asystentem rodzica {}. Analizujesz oceny i wyciągasz konstruktywne uwagi nauczycieli. Odpowiadasz TYLKO JSON. Podsumowanie to Twoja analiza, nie cytat.", profile.audience());
+
+        self.chat_json(&system, &prompt).await
+    }
+
+    async fn summarize_homework(
+        &self,
+        items: &[HomeworkItem],
+        profile: &StudentProfile,
+    ) -> Result<Analysis> {
+        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                format!(
+                    "{}. {}\n   Id: {}\n   Przedmiot: {}\n   Nauczyciel: {}\n   Typ: {}\n   Od: {}\n   Do: {}\n   Treść: {}",
+                    idx + 1,
+                    item.title,
+                    item.id,
+                    item.subject,
+                    item.user,
+                    item.kind,
+                    item.from,
+                    item.to,
+                    item.content
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!(
+            "DZISIAJ JEST: {}\n\nPrzeanalizuj zadania domowe dla {}.\n\nKONTEKST: {}\n\nZADANIA DOMOWE:\n{}\n\nIGNORUJ zadania z terminem który już minął.\n\nPILNOŚĆ (względem dzisiejszej daty):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-7 dni\n- NIEPILNE: termin > 7 dni\n\nFORMAT: Podsumowanie (2-3 zdania). keyPoints: posortowane od najpilniejszych.\n{}\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co trzeba zrobić\", \"keyPoints\": [\"📝 **przedmiot** - zadanie | ⏰ termin\"], {}}}",
+            today,
+            profile.class_description(),
+            profile.context(),
+            items_text,
+            ACTION_ITEMS_RULE,
+            ACTION_ITEMS_JSON
+        );
+
+        let system = format!("Jesteś asystentem rodzica {}. Analizujesz zadania domowe, ignorujesz minione terminy. Odpowiadasz TYLKO JSON.", profile.audience());
+
+        self.chat_json(&system, &prompt).await
+    }
+
+    async fn summarize_events(
+        &self,
+        items: &[EventItem],
+        profile: &StudentProfile,
+    ) -> Result<Analysis> {
+        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                format!(
+                    "{}. {}\n   Id: {}\n   Data: {}\n   Opis: {}",
+                    idx + 1,
+                    item.title,
+                    item.id,
+                    item.day,
+                    item.description
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!(
+            "DZISIAJ JEST: {}\n\nPrzeanalizuj nowe wpisy z terminarza szkolnego dla {}.\n\nKONTEKST: {}\n\nWYDARZENIA:\n{}\n\nRODZAJE WPISÓW:\n- SPRAWDZIANY/KARTKÓWKI: data, przedmiot, zakres materiału\n- WYCIECZKI/WYJŚCIA: data, godziny, co zabrać, koszt\n- WYDARZENIA KLASOWE: uroczystości, zebrania, dni wolne\n\nIGNORUJ wydarzenia które już minęły.\n\nPILNOŚĆ (względem dzisiejszej daty):\n- PILNE: wydarzenie w ciągu 3 dni lub wymaga przygotowania\n- NORMALNE: wydarzenie w ciągu 4-14 dni\n- NIEPILNE: wydarzenie > 14 dni lub informacyjne\n\nFORMAT: Podsumowanie (2-3 zdania) to Twoja analiza - NIE cytuj treści. keyPoints: posortowane chronologicznie.\n{}\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co nas czeka i jak się przygotować\", \"keyPoints\": [\"📅 **data** - wydarzenie | ⏰ godzina\"], {}}}",
+            today,
+            profile.class_description(),
+            profile.context(),
+            items_text,
+            ACTION_ITEMS_RULE,
+            ACTION_ITEMS_JSON
+        );
+
+        let system = format!("Jesteś asystentem rodzica {}. Analizujesz terminarz szkolny: sprawdziany, wycieczki i wydarzenia klasowe. Ignorujesz minione terminy. Odpowiadasz TYLKO JSON.", profile.audience());
+
+        self.chat_json(&system, &prompt).await
+    }
+}
+
+/// Builds the backend selected by `llm.provider` (`LLM_PROVIDER`).
+pub fn analyzer_from_config(config: &LlmConfig) -> Box<dyn Analyzer> {
+    match config.provider {
+        LlmProvider::Openai => Box::new(OpenAiClient::new(&config.openai, &config.retry)),
+        LlmProvider::OpenaiCompatible => {
+            Box::new(OpenAiClient::compatible(&config.compatible, &config.retry))
+        }
+        LlmProvider::Anthropic => Box::new(AnthropicClient::new(&config.anthropic, &config.retry)),
+    }
+}
+
+/// Parses and normalises the model reply, tolerating a Markdown code fence around
+/// the JSON.
+pub fn parse_analysis(content: &str) -> Result<Analysis> {
+    let trimmed = content.trim();
+    let json = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str::<Analysis>(json.trim())
+        .map_err(|err| anyhow!(err))
+        .context("LLM JSON content parse failed")?
+        .normalize()
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Serves a single canned JSON response and returns the base URL and the raw request.
+    pub async fn stub_server(response_body: String) -> (String, tokio::task::JoinHandle<String>) {
+        let (base_url, handle) = stub_server_sequence(vec![(200, response_body)]).await;
+        let handle = tokio::spawn(async move { handle.await.unwrap().remove(0) });
+        (base_url, handle)
+    }
+
+    /// Answers consecutive requests with the given `(status, body)` pairs, one per
+    /// connection, and returns the base URL and the raw requests.
+    pub async fn stub_server_sequence(
+        responses: Vec<(u16, String)>,
+    ) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, response_body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut socket).await);
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response_body.len(),
+                    response_body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (base_url, handle)
+    }
+
+    async fn read_request(socket: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if request.len() >= header_end + 4 + content_length || n == 0 {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&request).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ActionKind, Urgency};
+
+    #[test]
+    fn test_parse_analysis_plain_and_fenced() {
+        let json = r#"{"urgency": "PILNE", "summary": "Wycieczka", "keyPoints": ["💰 **20 zł**"]}"#;
+        assert_eq!(parse_analysis(json).unwrap().urgency, Urgency::Pilne);
+
+        let fenced = format!("```json\n{}\n```", json);
+        let analysis = parse_analysis(&fenced).unwrap();
+        assert_eq!(analysis.key_points, vec!["💰 **20 zł**"]);
+        assert!(analysis.action_items.is_empty());
+
+        let with_actions = r#"{"urgency": "PILNE", "summary": "Wycieczka", "keyPoints": [], "actionItems": [{"kind": "payment", "due": "2025-03-14", "amountPln": 20, "description": "Wpłata na wycieczkę", "sourceId": "42"}, {"kind": "homework", "description": "Zeszyt", "sourceId": 7}]}"#;
+        let actions = parse_analysis(with_actions).unwrap().action_items;
+        assert_eq!(actions[0].kind, ActionKind::Payment);
+        assert_eq!(actions[0].amount_pln, Some(20.0));
+        assert_eq!(actions[0].source_id.as_deref(), Some("42"));
+        assert_eq!(actions[1].kind, ActionKind::Other);
+        assert_eq!(actions[1].due, None);
+        assert_eq!(actions[1].source_id.as_deref(), Some("7"));
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{parse_analysis, Analyzer};
+use crate::config::{AnthropicConfig, RetryConfig};
+use crate::models::Analysis;
+use crate::retry::send_with_retry;
+
+pub const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Messages API client for Anthropic models.
+#[derive(Clone)]
+pub struct AnthropicClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    retry: RetryConfig,
+}
+
+impl AnthropicClient {
+    pub fn new(config: &AnthropicConfig, retry: &RetryConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+            model: config.model.clone(),
+            retry: retry.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Analyzer for AnthropicClient {
+    fn name(&self) -> &str {
+        "Anthropic"
+    }
+
+    async fn chat_json(&self, system: &str, user: &str) -> Result<Analysis> {
+        let request = AnthropicRequest {
+            model: self.model.clone(),
+            max_tokens: 2048,
+            system: system.to_string(),
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: user.to_string(),
+            }],
+            temperature: 0.3,
+        };
+
+        let body = send_with_retry(&self.retry, "Anthropic", || {
+            self.http
+                .post(format!("{}/messages", self.base_url))
+                .header(CONTENT_TYPE, "application/json")
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .json(&request)
+        })
+        .await?;
+
+        let parsed: AnthropicResponse =
+            serde_json::from_str(&body).context("Anthropic response parse failed")?;
+        let content = parsed
+            .content
+            .iter()
+            .find(|block| block.r#type == "text")
+            .and_then(|block| block.text.clone())
+            .ok_or_else(|| anyhow!("Anthropic response missing text content"))?;
+
+        parse_analysis(&content)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<AnthropicMessage>,
+    temperature: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    r#type: String,
+    text: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server;
+    use crate::models::Urgency;
+
+    #[tokio::test]
+    async fn test_messages_api_against_stub_server() {
+        let text = "```json\n{\"urgency\": \"PILNE\", \"summary\": \"Sprawdzian\", \"keyPoints\": []}\n```";
+        let body = serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+        .to_string();
+        let (base_url, request) = stub_server(body).await;
+
+        let client = AnthropicClient {
+            http: reqwest::Client::new(),
+            base_url,
+            api_key: "test-key".to_string(),
+            model: "claude-test".to_string(),
+            retry: RetryConfig::default(),
+        };
+        let analysis = client.chat_json("system", "user").await.unwrap();
+        assert_eq!(analysis.urgency, Urgency::Pilne);
+
+        let request = request.await.unwrap().to_lowercase();
+        assert!(request.starts_with("post /messages"));
+        assert!(request.contains("x-api-key: test-key"));
+        assert!(request.contains("anthropic-version: 2023-06-01"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::Scope;
+use crate::db::ItemKind;
+
+/// Librus notifications with AI summaries.
+///
+/// Without a subcommand the service runs once, as the systemd timer expects.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// TOML config file; `./config.toml` is used when present
+    #[arg(long, global = true, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum Command {
+    /// Fetch new items, analyse them and send notifications
+    Run {
+        /// Keep running and repeat on DAEMON_SCHEDULE until SIGTERM
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Fetch and analyse new items and print the email, without saving or sending anything
+    DryRun,
+    /// Mark everything currently in Librus as seen without notifying (first install)
+    Backfill,
+    /// List stored items
+    Show {
+        kind: ItemKind,
+        /// Maximum number of items per account
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Forget stored items so the next run reports them again
+    Reset {
+        kind: ItemKind,
+        /// Forget only the item with this Librus id
+        #[arg(long)]
+        id: Option<String>,
+    },
+    /// List action items the model extracted, such as payments and tests
+    Tasks {
+        /// Include tasks already marked as done
+        #[arg(long)]
+        all: bool,
+    },
+    /// Mark tasks as done
+    Done {
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+    /// Write all stored homework, events and open tasks to an iCalendar file
+    ExportIcs {
+        /// Output file, e.g. one served for calendar subscriptions
+        #[arg(long, short, default_value = "librus.ics")]
+        output: PathBuf,
+    },
+}
+
+impl Command {
+    /// The configuration the command needs validated.
+    pub fn config_scope(&self) -> Scope {
+        match self {
+            Command::Run { .. } => Scope::Full,
+            Command::DryRun => Scope::Analyze,
+            Command::Backfill => Scope::Librus,
+            Command::Show { .. }
+            | Command::Reset { .. }
+            | Command::Tasks { .. }
+            | Command::Done { .. }
+            | Command::ExportIcs { .. } => Scope::Offline,
+        }
+    }
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Run { daemon: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Command {
+        Cli::try_parse_from(std::iter::once("librus-notifications").chain(args.iter().copied()))
+            .unwrap()
+            .command
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_parses_subcommands() {
+        assert_eq!(parse(&[]), Command::Run { daemon: false });
+        assert_eq!(parse(&["run", "--daemon"]), Command::Run { daemon: true });
+        assert_eq!(parse(&["dry-run"]), Command::DryRun);
+        assert_eq!(
+            parse(&["show", "grades", "--limit", "5"]),
+            Command::Show {
+                kind: ItemKind::Grades,
+                limit: 5
+            }
+        );
+        assert_eq!(
+            parse(&["reset", "messages", "--id", "42"]),
+            Command::Reset {
+                kind: ItemKind::Messages,
+                id: Some("42".to_string())
+            }
+        );
+        assert_eq!(
+            parse(&["export-ics", "-o", "/srv/librus.ics"]),
+            Command::ExportIcs {
+                output: PathBuf::from("/srv/librus.ics")
+            }
+        );
+        assert_eq!(
+            parse(&["done", "3", "5"]),
+            Command::Done { ids: vec![3, 5] }
+        );
+        assert!(Cli::try_parse_from(["librus-notifications", "done"]).is_err());
+        assert!(Cli::try_parse_from(["librus-notifications", "show", "teachers"]).is_err());
+    }
+}
@@ -0,0 +1,971 @@
+use std::env;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::accounts::{self, Account};
+use crate::anthropic::ANTHROPIC_BASE_URL;
+use crate::delivery::Delivery;
+use crate::models::Urgency;
+use crate::ntfy::NTFY_SERVER;
+use crate::routing::SectionKind;
+use crate::schedule::Schedule;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./config.toml";
+const DEFAULT_DB_PATH: &str = "./librus.db";
+// Same times as the bundled systemd timer
+const DEFAULT_SCHEDULE: &str = "0 7,15 * * *";
+
+/// The parts of the configuration a command uses, each including the ones before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// Only the database and the account list, e.g. `show` and `tasks`.
+    Offline,
+    /// Also Librus credentials, for `backfill`.
+    Librus,
+    /// Also the LLM provider, for `dry-run`.
+    Analyze,
+    /// Everything, including notification channels and the schedule.
+    Full,
+}
+
+/// Complete service configuration.
+///
+/// Values come from the TOML file first; every setting can be overridden by its
+/// environment variable, listed in [`Config::apply_env`]. Accounts defined through the
+/// environment replace those from the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db_path: String,
+    pub log_level: String,
+    pub accounts: Vec<Account>,
+    pub librus: LibrusConfig,
+    pub llm: LlmConfig,
+    pub notify: NotifyConfig,
+    pub reminders: ReminderConfig,
+    pub daemon: DaemonConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            db_path: DEFAULT_DB_PATH.to_string(),
+            log_level: "INFO".to_string(),
+            accounts: Vec::new(),
+            librus: LibrusConfig::default(),
+            llm: LlmConfig::default(),
+            notify: NotifyConfig::default(),
+            reminders: ReminderConfig::default(),
+            daemon: DaemonConfig::default(),
+        }
+    }
+}
+
+/// How much history is fetched from Librus on each run.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibrusConfig {
+    pub announcements_limit: usize,
+    pub messages_max_pages: u32,
+    pub messages_page_size: u32,
+    pub attendance_days: i64,
+    pub timetable_days: i64,
+}
+
+impl Default for LibrusConfig {
+    fn default() -> Self {
+        Self {
+            announcements_limit: 50,
+            messages_max_pages: 10,
+            messages_page_size: 50,
+            attendance_days: 14,
+            timetable_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LlmProvider {
+    #[default]
+    Openai,
+    #[serde(alias = "ollama")]
+    OpenaiCompatible,
+    Anthropic,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    pub provider: LlmProvider,
+    pub openai: OpenAiConfig,
+    pub anthropic: AnthropicConfig,
+    pub compatible: CompatibleConfig,
+    pub retry: RetryConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAiConfig {
+    pub api_key: String,
+    pub model: String,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            model: "gpt-5.2".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnthropicConfig {
+    pub api_key: String,
+    pub model: String,
+    pub base_url: String,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            model: "claude-sonnet-4-5".to_string(),
+            base_url: ANTHROPIC_BASE_URL.to_string(),
+        }
+    }
+}
+
+/// Any server exposing the OpenAI `/chat/completions` API (Ollama, llama.cpp, vLLM).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompatibleConfig {
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// Send a strict JSON schema (structured outputs) instead of plain JSON mode;
+    /// recent Ollama, llama.cpp server and vLLM versions support it.
+    pub json_schema: bool,
+}
+
+/// Retries of LLM calls failing with rate limits, server errors or timeouts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Per-request timeout, covering the whole response.
+    pub timeout_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+            timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Email,
+    Telegram,
+    Webhook,
+    Ntfy,
+    Matrix,
+    Discord,
+    Slack,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub channels: Vec<Channel>,
+    pub email: EmailConfig,
+    pub telegram: TelegramConfig,
+    pub webhook: WebhookConfig,
+    pub ntfy: NtfyConfig,
+    pub matrix: MatrixConfig,
+    pub discord: ChatWebhookConfig,
+    pub slack: ChatWebhookConfig,
+    /// `[[notify.routes]]`; only settable in the config file.
+    pub routes: Vec<RouteRule>,
+    pub digest: DigestConfig,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec![Channel::Email],
+            email: EmailConfig::default(),
+            telegram: TelegramConfig::default(),
+            webhook: WebhookConfig::default(),
+            ntfy: NtfyConfig::default(),
+            matrix: MatrixConfig::default(),
+            discord: ChatWebhookConfig::default(),
+            slack: ChatWebhookConfig::default(),
+            routes: Vec::new(),
+            digest: DigestConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestMode {
+    /// Every run sends what it found.
+    #[default]
+    Instant,
+    Daily,
+    Weekly,
+}
+
+/// Holding back non-urgent sections for a daily or weekly digest, see
+/// [`Delivery`](crate::delivery::Delivery).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    pub mode: DigestMode,
+    /// `HH:MM` local time of the digest; the first run from then on sends it.
+    pub time: String,
+    /// Day of the weekly digest, e.g. `sun`.
+    pub weekday: String,
+    /// `HH:MM-HH:MM`, may span midnight; empty for none.
+    pub quiet_hours: String,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            mode: DigestMode::Instant,
+            time: "18:00".to_string(),
+            weekday: "sun".to_string(),
+            quiet_hours: String::new(),
+        }
+    }
+}
+
+/// Sends the digest sections it matches to its own channels and email recipients
+/// instead of everywhere. Empty conditions match anything; empty targets fall back
+/// to `notify.channels` and `notify.email.to`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteRule {
+    pub name: String,
+    pub sections: Vec<SectionKind>,
+    pub min_urgency: Option<Urgency>,
+    /// Case-insensitive; any of them in the analysis or item text is enough.
+    pub keywords: Vec<String>,
+    pub channels: Vec<Channel>,
+    pub email_to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    /// Without it the email is printed instead of sent.
+    pub send: bool,
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    /// Sender address; the SMTP user when empty.
+    pub from: String,
+    pub to: Vec<String>,
+    pub per_child: bool,
+    pub template_dir: Option<PathBuf>,
+    /// Attach the dated items of the email as an `.ics` file.
+    pub attach_ics: bool,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            send: false,
+            host: String::new(),
+            port: 587,
+            user: String::new(),
+            password: String::new(),
+            from: String::new(),
+            to: Vec::new(),
+            per_child: false,
+            template_dir: None,
+            attach_ics: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub bearer_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NtfyConfig {
+    pub topic: String,
+    pub server: String,
+    pub token: Option<String>,
+}
+
+impl Default for NtfyConfig {
+    fn default() -> Self {
+        Self {
+            topic: String::new(),
+            server: NTFY_SERVER.to_string(),
+            token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatrixConfig {
+    pub homeserver: String,
+    pub access_token: String,
+    pub room_id: String,
+}
+
+/// Discord or Slack incoming webhook.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatWebhookConfig {
+    pub webhook_url: String,
+}
+
+/// Reminders about stored homework and events shortly before they fall due.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReminderConfig {
+    /// How many days before the due date to remind; 0 turns reminders off.
+    pub days_ahead: u32,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        Self { days_ahead: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Cron expression, see [`Schedule`].
+    pub schedule: String,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            schedule: DEFAULT_SCHEDULE.to_string(),
+        }
+    }
+}
+
+/// Reads overrides from the environment, collecting values that fail to parse.
+struct EnvOverrides<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>,
+}
+
+impl EnvOverrides<'_> {
+    fn get(&self, name: &str) -> Option<String> {
+        (self.lookup)(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn string(&self, name: &str, target: &mut String) {
+        if let Some(value) = self.get(name) {
+            *target = value;
+        }
+    }
+
+    fn optional<T: From<String>>(&self, name: &str, target: &mut Option<T>) {
+        if let Some(value) = self.get(name) {
+            *target = Some(value.into());
+        }
+    }
+
+    fn list(&self, name: &str, target: &mut Vec<String>) {
+        if let Some(value) = self.get(name) {
+            *target = split_list(&value);
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, target: &mut T)
+    where
+        T::Err: Display,
+    {
+        if let Some(value) = self.get(name) {
+            match value.parse() {
+                Ok(parsed) => *target = parsed,
+                Err(err) => self.errors.push(format!("{}='{}': {}", name, value, err)),
+            }
+        }
+    }
+
+    /// Parses a comma separated list of the names `T` accepts in the config file.
+    fn variants<T: DeserializeOwned>(&mut self, name: &str, target: &mut Vec<T>) {
+        if let Some(value) = self.get(name) {
+            let parsed: Result<Vec<T>, ValueError> = split_list(&value)
+                .iter()
+                .map(|item| T::deserialize(StrDeserializer::new(&item.to_lowercase())))
+                .collect();
+            match parsed {
+                Ok(parsed) => *target = parsed,
+                Err(err) => self.errors.push(format!("{}='{}': {}", name, value, err)),
+            }
+        }
+    }
+
+    fn variant<T: DeserializeOwned>(&mut self, name: &str, target: &mut T) {
+        let mut parsed = Vec::new();
+        self.variants(name, &mut parsed);
+        if let Some(value) = parsed.pop() {
+            *target = value;
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Config {
+    /// Loads the file at `path` (or `./config.toml` when it exists), applies environment
+    /// overrides and validates what `scope` uses, reporting every problem at once.
+    pub fn load(path: Option<&Path>, scope: Scope) -> Result<Self> {
+        Self::load_with(path, scope, &|name| env::var(name).ok())
+    }
+
+    fn load_with(
+        path: Option<&Path>,
+        scope: Scope,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let default_path = Path::new(DEFAULT_CONFIG_PATH);
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if default_path.exists() => Self::from_file(default_path)?,
+            None => Self::default(),
+        };
+
+        let mut env = EnvOverrides {
+            lookup,
+            errors: Vec::new(),
+        };
+        config.apply_env(&mut env)?;
+        for account in &mut config.accounts {
+            let fallback_name = if account.id.is_empty() {
+                "Dziecko"
+            } else {
+                &account.id
+            };
+            account.profile.fill_defaults(fallback_name);
+        }
+
+        let mut errors = env.errors;
+        errors.extend(config.validate(scope));
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    fn apply_env(&mut self, env: &mut EnvOverrides) -> Result<()> {
+        if let Some(accounts) = accounts::accounts_from_env(env.lookup)? {
+            self.accounts = accounts;
+        }
+
+        env.string("DB_PATH", &mut self.db_path);
+        env.string("LOG_LEVEL", &mut self.log_level);
+        env.string("DAEMON_SCHEDULE", &mut self.daemon.schedule);
+        env.parse("REMINDER_DAYS_AHEAD", &mut self.reminders.days_ahead);
+
+        let librus = &mut self.librus;
+        env.parse(
+            "LIBRUS_ANNOUNCEMENTS_LIMIT",
+            &mut librus.announcements_limit,
+        );
+        env.parse("LIBRUS_MESSAGES_MAX_PAGES", &mut librus.messages_max_pages);
+        env.parse("LIBRUS_MESSAGES_PAGE_SIZE", &mut librus.messages_page_size);
+        env.parse("LIBRUS_ATTENDANCE_DAYS", &mut librus.attendance_days);
+        env.parse("LIBRUS_TIMETABLE_DAYS", &mut librus.timetable_days);
+
+        let llm = &mut self.llm;
+        env.variant("LLM_PROVIDER", &mut llm.provider);
+        env.string("OPENAI_API_KEY", &mut llm.openai.api_key);
+        env.string("OPENAI_MODEL", &mut llm.openai.model);
+        env.string("ANTHROPIC_API_KEY", &mut llm.anthropic.api_key);
+        env.string("ANTHROPIC_MODEL", &mut llm.anthropic.model);
+        env.string("ANTHROPIC_BASE_URL", &mut llm.anthropic.base_url);
+        env.string("LLM_BASE_URL", &mut llm.compatible.base_url);
+        env.string("LLM_MODEL", &mut llm.compatible.model);
+        env.optional("LLM_API_KEY", &mut llm.compatible.api_key);
+        env.parse("LLM_JSON_SCHEMA", &mut llm.compatible.json_schema);
+        env.parse("LLM_MAX_RETRIES", &mut llm.retry.max_retries);
+        env.parse("LLM_RETRY_BACKOFF_MS", &mut llm.retry.initial_backoff_ms);
+        env.parse("LLM_RETRY_MAX_BACKOFF_MS", &mut llm.retry.max_backoff_ms);
+        env.parse("LLM_TIMEOUT_SECS", &mut llm.retry.timeout_secs);
+
+        let notify = &mut self.notify;
+        env.variant("DIGEST_MODE", &mut notify.digest.mode);
+        env.string("DIGEST_TIME", &mut notify.digest.time);
+        env.string("DIGEST_WEEKDAY", &mut notify.digest.weekday);
+        env.string("QUIET_HOURS", &mut notify.digest.quiet_hours);
+        env.variants("NOTIFY_CHANNELS", &mut notify.channels);
+        env.parse("SEND_EMAIL", &mut notify.email.send);
+        env.string("EMAIL_HOST", &mut notify.email.host);
+        env.parse("EMAIL_PORT", &mut notify.email.port);
+        env.string("EMAIL_USER", &mut notify.email.user);
+        env.string("EMAIL_PASSWORD", &mut notify.email.password);
+        env.string("EMAIL_FROM", &mut notify.email.from);
+        env.list("EMAIL_TO", &mut notify.email.to);
+        env.parse("EMAIL_PER_CHILD", &mut notify.email.per_child);
+        env.optional("EMAIL_TEMPLATE_DIR", &mut notify.email.template_dir);
+        env.parse("EMAIL_ATTACH_ICS", &mut notify.email.attach_ics);
+        env.string("TELEGRAM_BOT_TOKEN", &mut notify.telegram.bot_token);
+        env.list("TELEGRAM_CHAT_IDS", &mut notify.telegram.chat_ids);
+        env.string("WEBHOOK_URL", &mut notify.webhook.url);
+        env.optional("WEBHOOK_BEARER_TOKEN", &mut notify.webhook.bearer_token);
+        env.string("NTFY_TOPIC", &mut notify.ntfy.topic);
+        env.string("NTFY_SERVER", &mut notify.ntfy.server);
+        env.optional("NTFY_TOKEN", &mut notify.ntfy.token);
+        env.string("MATRIX_HOMESERVER", &mut notify.matrix.homeserver);
+        env.string("MATRIX_ACCESS_TOKEN", &mut notify.matrix.access_token);
+        env.string("MATRIX_ROOM_ID", &mut notify.matrix.room_id);
+        env.string("DISCORD_WEBHOOK_URL", &mut notify.discord.webhook_url);
+        env.string("SLACK_WEBHOOK_URL", &mut notify.slack.webhook_url);
+        Ok(())
+    }
+
+    /// Every problem with the parts of the configuration `scope` uses, named by file key
+    /// and variable.
+    fn validate(&self, scope: Scope) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut require = |value: &str, key: &str, var: &str, reason: &str| {
+            if value.trim().is_empty() {
+                errors.push(format!("{} ({}) must be set {}", key, var, reason));
+            }
+        };
+
+        if scope >= Scope::Analyze {
+            let llm = &self.llm;
+            match llm.provider {
+                LlmProvider::Openai => {
+                    require(
+                        &llm.openai.api_key,
+                        "llm.openai.api_key",
+                        "OPENAI_API_KEY",
+                        "for the openai provider",
+                    );
+                }
+                LlmProvider::Anthropic => {
+                    require(
+                        &llm.anthropic.api_key,
+                        "llm.anthropic.api_key",
+                        "ANTHROPIC_API_KEY",
+                        "for the anthropic provider",
+                    );
+                }
+                LlmProvider::OpenaiCompatible => {
+                    let reason = "for the openai-compatible provider";
+                    require(
+                        &llm.compatible.base_url,
+                        "llm.compatible.base_url",
+                        "LLM_BASE_URL",
+                        reason,
+                    );
+                    require(
+                        &llm.compatible.model,
+                        "llm.compatible.model",
+                        "LLM_MODEL",
+                        reason,
+                    );
+                }
+            }
+        }
+
+        if scope == Scope::Full {
+            let notify = &self.notify;
+            for channel in &notify.channels {
+                match channel {
+                    Channel::Email if notify.email.send => {
+                        let reason = "when email sending is enabled";
+                        require(
+                            &notify.email.host,
+                            "notify.email.host",
+                            "EMAIL_HOST",
+                            reason,
+                        );
+                        require(
+                            &notify.email.user,
+                            "notify.email.user",
+                            "EMAIL_USER",
+                            reason,
+                        );
+                        require(
+                            &notify.email.password,
+                            "notify.email.password",
+                            "EMAIL_PASSWORD",
+                            reason,
+                        );
+                        require(
+                            &notify.email.to.join(","),
+                            "notify.email.to",
+                            "EMAIL_TO",
+                            reason,
+                        );
+                    }
+                    Channel::Email => {}
+                    Channel::Telegram => {
+                        let reason = "for the telegram channel";
+                        require(
+                            &notify.telegram.bot_token,
+                            "notify.telegram.bot_token",
+                            "TELEGRAM_BOT_TOKEN",
+                            reason,
+                        );
+                        require(
+                            &notify.telegram.chat_ids.join(","),
+                            "notify.telegram.chat_ids",
+                            "TELEGRAM_CHAT_IDS",
+                            reason,
+                        );
+                    }
+                    Channel::Webhook => {
+                        require(
+                            &notify.webhook.url,
+                            "notify.webhook.url",
+                            "WEBHOOK_URL",
+                            "for the webhook channel",
+                        );
+                    }
+                    Channel::Ntfy => {
+                        require(
+                            &notify.ntfy.topic,
+                            "notify.ntfy.topic",
+                            "NTFY_TOPIC",
+                            "for the ntfy channel",
+                        );
+                    }
+                    Channel::Matrix => {
+                        let reason = "for the matrix channel";
+                        require(
+                            &notify.matrix.homeserver,
+                            "notify.matrix.homeserver",
+                            "MATRIX_HOMESERVER",
+                            reason,
+                        );
+                        require(
+                            &notify.matrix.access_token,
+                            "notify.matrix.access_token",
+                            "MATRIX_ACCESS_TOKEN",
+                            reason,
+                        );
+                        require(
+                            &notify.matrix.room_id,
+                            "notify.matrix.room_id",
+                            "MATRIX_ROOM_ID",
+                            reason,
+                        );
+                    }
+                    Channel::Discord => {
+                        require(
+                            &notify.discord.webhook_url,
+                            "notify.discord.webhook_url",
+                            "DISCORD_WEBHOOK_URL",
+                            "for the discord channel",
+                        );
+                    }
+                    Channel::Slack => {
+                        require(
+                            &notify.slack.webhook_url,
+                            "notify.slack.webhook_url",
+                            "SLACK_WEBHOOK_URL",
+                            "for the slack channel",
+                        );
+                    }
+                }
+            }
+            if notify.channels.is_empty() {
+                errors.push(
+                    "notify.channels (NOTIFY_CHANNELS) must list at least one channel".to_string(),
+                );
+            }
+            for (index, rule) in notify.routes.iter().enumerate() {
+                if rule.name.trim().is_empty() {
+                    errors.push(format!("notify.routes[{}]: name must be set", index));
+                }
+                for channel in &rule.channels {
+                    if !notify.channels.contains(channel) {
+                        errors.push(format!(
+                            "notify.routes[{}] ({}): channel {} is not in notify.channels",
+                            index,
+                            rule.name,
+                            format!("{:?}", channel).to_lowercase()
+                        ));
+                    }
+                }
+            }
+        }
+
+        if scope >= Scope::Analyze && self.llm.retry.timeout_secs == 0 {
+            errors.push("llm.retry.timeout_secs (LLM_TIMEOUT_SECS) must be positive".to_string());
+        }
+
+        if scope == Scope::Full {
+            if let Err(err) = Delivery::from_config(&self.notify.digest) {
+                errors.push(format!("notify.digest: {:#}", err));
+            }
+            if let Err(err) = self.daemon.schedule.parse::<Schedule>() {
+                errors.push(format!("daemon.schedule (DAEMON_SCHEDULE): {:#}", err));
+            }
+        }
+
+        errors.extend(validate_accounts(&self.accounts, scope >= Scope::Librus));
+        errors
+    }
+}
+
+/// Credentials are only checked for commands that log in to Librus.
+fn validate_accounts(accounts: &[Account], need_credentials: bool) -> Vec<String> {
+    let mut errors = Vec::new();
+    if accounts.is_empty() {
+        errors.push(
+            "No Librus account: set LIBRUS_USERNAME and LIBRUS_PASSWORD, LIBRUS_ACCOUNTS or [[accounts]] in the config file"
+                .to_string(),
+        );
+    }
+    for (index, account) in accounts.iter().enumerate() {
+        if need_credentials && (account.username.is_empty() || account.password.is_empty()) {
+            errors.push(format!(
+                "accounts[{}] ({}): username and password must be set",
+                index, account.profile.name
+            ));
+        }
+        if accounts.len() > 1 {
+            if account.id.is_empty()
+                || !account
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                errors.push(format!(
+                    "accounts[{}]: id '{}' must be set and use letters, digits and '_' when there are several accounts",
+                    index, account.id
+                ));
+            }
+            if accounts[..index].iter().any(|other| other.id == account.id) {
+                errors.push(format!(
+                    "accounts[{}]: duplicate id '{}'",
+                    index, account.id
+                ));
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    fn load_scoped(file: &str, scope: Scope, vars: &[(&str, &str)]) -> Result<Config> {
+        let path = std::env::temp_dir().join(format!(
+            "librus-config-{}-{}.toml",
+            std::process::id(),
+            FILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::write(&path, file).unwrap();
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let config = Config::load_with(Some(&path), scope, &|name| vars.get(name).cloned());
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    fn load(file: &str, vars: &[(&str, &str)]) -> Result<Config> {
+        load_scoped(file, Scope::Full, vars)
+    }
+
+    const FILE: &str = r#"
+        db_path = "/var/lib/czujka/librus.db"
+
+        [[accounts]]
+        id = "emilka"
+        username = "login_emilki"
+        password = "haslo"
+        student = { class = "1A", ignore_patterns = ["4-8 SP"] }
+
+        [[accounts]]
+        id = "jas"
+        username = "login_jasia"
+        password = "haslo"
+        student = { name = "Jaś" }
+
+        [llm]
+        provider = "anthropic"
+        anthropic = { api_key = "sk-ant" }
+
+        [notify]
+        channels = ["email", "ntfy"]
+        email = { send = true, host = "smtp.example.com", user = "czujka", password = "x", to = ["a@example.com"] }
+        ntfy = { topic = "librus" }
+
+        [[notify.routes]]
+        name = "oceny"
+        sections = ["grades"]
+        email_to = ["mama@example.com"]
+
+        [[notify.routes]]
+        name = "pilne"
+        min_urgency = "pilne"
+        keywords = ["wycieczka"]
+        channels = ["email"]
+    "#;
+
+    #[test]
+    fn test_file_values_with_env_overrides() {
+        let config = load(
+            FILE,
+            &[
+                ("EMAIL_PORT", "465"),
+                ("NOTIFY_CHANNELS", "email, Telegram"),
+                ("TELEGRAM_BOT_TOKEN", "t"),
+                ("TELEGRAM_CHAT_IDS", "1,2"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.db_path, "/var/lib/czujka/librus.db");
+        assert_eq!(config.accounts[0].profile.name, "emilka");
+        assert_eq!(config.accounts[0].profile.class_name, "1A");
+        assert!(!config.accounts[0].profile.grading_scale.is_empty());
+        assert_eq!(config.accounts[1].profile.name, "Jaś");
+        assert_eq!(config.llm.provider, LlmProvider::Anthropic);
+        assert_eq!(config.llm.anthropic.model, "claude-sonnet-4-5");
+        assert_eq!(config.notify.email.port, 465);
+        assert_eq!(
+            config.notify.channels,
+            vec![Channel::Email, Channel::Telegram]
+        );
+        assert_eq!(config.notify.telegram.chat_ids, vec!["1", "2"]);
+        assert_eq!(config.librus.messages_max_pages, 10);
+        let routes = &config.notify.routes;
+        assert_eq!(routes[0].sections, vec![SectionKind::Grades]);
+        assert_eq!(routes[0].email_to, vec!["mama@example.com"]);
+        assert_eq!(routes[1].min_urgency, Some(Urgency::Pilne));
+        assert_eq!(routes[1].channels, vec![Channel::Email]);
+    }
+
+    #[test]
+    fn test_env_accounts_replace_file_accounts() {
+        let config = load(
+            FILE,
+            &[
+                ("LIBRUS_USERNAME", "u"),
+                ("LIBRUS_PASSWORD", "p"),
+                ("STUDENT_NAME", "Ola"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.accounts.len(), 1);
+        assert_eq!(config.accounts[0].id, "");
+        assert_eq!(config.accounts[0].profile.name, "Ola");
+    }
+
+    #[test]
+    fn test_reports_every_problem_at_once() {
+        let err = load(
+            "[notify]\nchannels = [\"matrix\"]\n[[notify.routes]]\nchannels = [\"slack\"]",
+            &[
+                ("EMAIL_PORT", "smtp"),
+                ("LLM_PROVIDER", "gemini"),
+                ("DAEMON_SCHEDULE", "daily"),
+                ("QUIET_HOURS", "22-7"),
+            ],
+        )
+        .unwrap_err()
+        .to_string();
+        for expected in [
+            "EMAIL_PORT='smtp'",
+            "LLM_PROVIDER='gemini': unknown variant `gemini`",
+            "llm.openai.api_key (OPENAI_API_KEY) must be set",
+            "notify.matrix.room_id (MATRIX_ROOM_ID) must be set for the matrix channel",
+            "notify.routes[0]: name must be set",
+            "notify.routes[0] (): channel slack is not in notify.channels",
+            "notify.digest: Invalid quiet hours '22-7'",
+            "daemon.schedule (DAEMON_SCHEDULE)",
+            "No Librus account",
+        ] {
+            assert!(
+                err.contains(expected),
+                "missing '{}' in:\n{}",
+                expected,
+                err
+            );
+        }
+
+        let err = load("colour = \"blue\"", &[]).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown field `colour`"));
+    }
+
+    #[test]
+    fn test_offline_commands_skip_unused_sections() {
+        let file = "[[accounts]]\nid = \"emilka\"\n[notify]\nchannels = [\"matrix\"]";
+        assert!(load_scoped(file, Scope::Offline, &[]).is_ok());
+
+        let err = load_scoped(file, Scope::Librus, &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("username and password must be set"));
+        assert!(!err.contains("OPENAI_API_KEY") && !err.contains("MATRIX_ROOM_ID"));
+
+        let err = load_scoped(file, Scope::Analyze, &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("OPENAI_API_KEY") && !err.contains("MATRIX_ROOM_ID"));
+    }
+}
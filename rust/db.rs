@@ -1,86 +1,485 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-use anyhow::Result;
-use chrono::Utc;
-use rusqlite::{params, Connection};
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::backup::Progress;
+use rusqlite::{params, Connection, DatabaseName, OptionalExtension, Params};
 
-use crate::models::{AnnouncementItem, GradeItem, HomeworkItem, MessageItem};
+use crate::models::{
+    ActionItem, ActionKind, AnnouncementItem, AttendanceItem, Digest, EventItem, GradeItem,
+    HomeworkItem, MessageItem, Task, TimetableLesson, TimetableSnapshot,
+};
+
+const ITEM_TABLES: &[&str] = &[
+    "announcements",
+    "messages",
+    "grades",
+    "events",
+    "homework",
+    "attendance",
+];
+
+/// Kinds of stored items, as named on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ItemKind {
+    Announcements,
+    Messages,
+    Grades,
+    Events,
+    Homework,
+    Attendance,
+}
+
+impl ItemKind {
+    pub fn table(self) -> &'static str {
+        match self {
+            ItemKind::Announcements => "announcements",
+            ItemKind::Messages => "messages",
+            ItemKind::Grades => "grades",
+            ItemKind::Events => "events",
+            ItemKind::Homework => "homework",
+            ItemKind::Attendance => "attendance",
+        }
+    }
+
+    /// One-line description of a row; `printf` turns NULL columns into empty text.
+    fn summary_sql(self) -> &'static str {
+        match self {
+            ItemKind::Announcements => "printf('%s (%s)', title, author)",
+            ItemKind::Messages => "printf('%s (%s)', title, sender)",
+            ItemKind::Grades => "printf('%s: %s', subject, value)",
+            ItemKind::Events => "printf('%s %s', day, title)",
+            ItemKind::Homework => "printf('%s: %s, do %s', subject, title, date_to)",
+            ItemKind::Attendance => {
+                "printf('%s lekcja %s: %s, %s', date, lesson_no, type, subject)"
+            }
+        }
+    }
+}
+
+// Local time, so queue timestamps compare directly with digest times
+const QUEUE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Digest sections waiting for the daily or weekly digest.
+#[derive(Debug, Clone)]
+pub struct QueuedDigest {
+    pub id: i64,
+    pub queued_at: NaiveDateTime,
+    pub digest: Digest,
+}
+
+/// A stored row as listed by `show`.
+#[derive(Debug, Clone)]
+pub struct StoredItem {
+    pub id: String,
+    pub notify_state: String,
+    pub fetched_at: String,
+    pub summary: String,
+}
+
+/// Delivery state of a stored item.
+///
+/// Rows start as `Pending` and only become `Notified` once the notification carrying
+/// them was delivered; `Skipped` rows are recorded but never reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyState {
+    Pending,
+    Notified,
+    Skipped,
+}
+
+impl NotifyState {
+    fn as_str(self) -> &'static str {
+        match self {
+            NotifyState::Pending => "pending",
+            NotifyState::Notified => "notified",
+            NotifyState::Skipped => "skipped",
+        }
+    }
+}
+
+/// Ordered schema migrations; the position in the list (starting at 1) is the
+/// version recorded in `schema_version`. Never edit an entry that has shipped,
+/// append a new one instead.
+const MIGRATIONS: &[fn(&Connection) -> Result<()>] = &[
+    migrate_base_tables,
+    migrate_attendance,
+    migrate_timetable,
+    migrate_notify_state,
+    migrate_grade_hash,
+    migrate_reminders,
+    migrate_tasks,
+    migrate_digest_queue,
+    migrate_timetable_account,
+    migrate_default_namespace,
+    migrate_grade_hash_comment_text,
+    migrate_task_keys,
+];
+
+/// Id namespace of the single-account setup, whose account has no id.
+const DEFAULT_NAMESPACE: &str = "default";
+
+// Early versions created tables without tracking, hence `IF NOT EXISTS` here
+fn migrate_base_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS announcements (
+          id TEXT PRIMARY KEY,
+          title TEXT,
+          content TEXT,
+          date TEXT,
+          author TEXT,
+          fetched_at TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS messages (
+          id TEXT PRIMARY KEY,
+          title TEXT,
+          content TEXT,
+          date TEXT,
+          sender TEXT,
+          fetched_at TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS grades (
+          id TEXT PRIMARY KEY,
+          subject TEXT,
+          value TEXT,
+          info TEXT,
+          fetched_at TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS events (
+          id TEXT PRIMARY KEY,
+          title TEXT,
+          day TEXT,
+          description TEXT,
+          fetched_at TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS homework (
+          id TEXT PRIMARY KEY,
+          subject TEXT,
+          title TEXT,
+          type TEXT,
+          date_from TEXT,
+          date_to TEXT,
+          content TEXT,
+          teacher TEXT,
+          fetched_at TEXT
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_attendance(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS attendance (
+          id TEXT PRIMARY KEY,
+          date TEXT,
+          lesson_no INTEGER,
+          subject TEXT,
+          type TEXT,
+          type_short TEXT,
+          fetched_at TEXT
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_timetable(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS timetable (
+          account TEXT NOT NULL DEFAULT '',
+          date TEXT,
+          lesson_no TEXT,
+          hour_from TEXT,
+          hour_to TEXT,
+          subject TEXT,
+          teacher TEXT,
+          canceled INTEGER,
+          substitution INTEGER,
+          fetched_at TEXT,
+          PRIMARY KEY (account, date, lesson_no, subject)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+// Rows stored before delivery tracking existed were already reported
+fn migrate_notify_state(conn: &Connection) -> Result<()> {
+    for table in ITEM_TABLES {
+        if !has_column(conn, table, "notify_state")? {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN notify_state TEXT NOT NULL DEFAULT 'notified'",
+                table
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+// Legacy rows keep an empty hash until the next fetch fills it in
+fn migrate_grade_hash(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "grades", "content_hash")? {
+        conn.execute_batch("ALTER TABLE grades ADD COLUMN content_hash TEXT NOT NULL DEFAULT ''")?;
+    }
+    Ok(())
+}
+
+// One row per reminder sent; a moved due date gets a new reminder
+fn migrate_reminders(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS reminders (
+          kind TEXT NOT NULL,
+          id TEXT NOT NULL,
+          due TEXT NOT NULL,
+          sent_at TEXT,
+          PRIMARY KEY (kind, id, due)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+// Action items extracted by the model; `key` identifies the same item across runs
+fn migrate_tasks(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS tasks (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          account TEXT NOT NULL DEFAULT '',
+          key TEXT NOT NULL,
+          kind TEXT NOT NULL,
+          due TEXT,
+          amount_pln REAL,
+          description TEXT NOT NULL,
+          source TEXT NOT NULL,
+          source_id TEXT,
+          created_at TEXT,
+          done_at TEXT,
+          UNIQUE (account, key)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+// Sections held back for the daily or weekly digest, as JSON, and when each
+// account's queue was last sent
+fn migrate_digest_queue(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS digest_queue (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          account TEXT NOT NULL DEFAULT '',
+          queued_at TEXT NOT NULL,
+          payload TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS digest_flushes (
+          account TEXT PRIMARY KEY,
+          flushed_at TEXT NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+// Timetables stored before multi-account support have neither the account column
+// nor the account in their key, so the table is rebuilt
+fn migrate_timetable_account(conn: &Connection) -> Result<()> {
+    if has_column(conn, "timetable", "account")? {
+        return Ok(());
+    }
+    conn.execute_batch(
+        r#"
+        ALTER TABLE timetable RENAME TO timetable_legacy;
+
+        CREATE TABLE timetable (
+          account TEXT NOT NULL DEFAULT '',
+          date TEXT,
+          lesson_no TEXT,
+          hour_from TEXT,
+          hour_to TEXT,
+          subject TEXT,
+          teacher TEXT,
+          canceled INTEGER,
+          substitution INTEGER,
+          fetched_at TEXT,
+          PRIMARY KEY (account, date, lesson_no, subject)
+        );
+
+        INSERT INTO timetable (date, lesson_no, hour_from, hour_to, subject, teacher, canceled, substitution, fetched_at)
+          SELECT date, lesson_no, hour_from, hour_to, subject, teacher, canceled, substitution, fetched_at
+          FROM timetable_legacy;
+
+        DROP TABLE timetable_legacy;
+        "#,
+    )?;
+    Ok(())
+}
+
+// Single-account ids used to be stored without a namespace. Unprefixed rows containing
+// ':' cannot be told apart from other accounts' rows and are left alone; they were
+// never matched back anyway
+fn migrate_default_namespace(conn: &Connection) -> Result<()> {
+    for table in ITEM_TABLES.iter().chain(&["reminders"]) {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET id = ? || ':' || id WHERE instr(id, ':') = 0",
+                table
+            ),
+            [DEFAULT_NAMESPACE],
+        )?;
+    }
+    Ok(())
+}
+
+// Grade hashes now cover the comment text; an empty hash makes the next fetch store
+// the new one without reporting every commented grade as edited
+fn migrate_grade_hash_comment_text(conn: &Connection) -> Result<()> {
+    conn.execute_batch("UPDATE grades SET content_hash = ''")?;
+    Ok(())
+}
+
+// Tasks naming their source element are now keyed on it; a task that would duplicate
+// another one keeps its old key
+fn migrate_task_keys(conn: &Connection) -> Result<()> {
+    let mut stmt =
+        conn.prepare("SELECT id, kind, due, description, source, source_id FROM tasks")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(4)?,
+            ActionItem {
+                kind: ActionKind::parse(&row.get::<_, String>(1)?),
+                due: row.get(2)?,
+                amount_pln: None,
+                description: row.get(3)?,
+                source_id: row.get(5)?,
+            },
+        ))
+    })?;
+    for row in rows {
+        let (id, source, item) = row?;
+        conn.execute(
+            "UPDATE OR IGNORE tasks SET key = ? WHERE id = ?",
+            params![item.key(&source), id],
+        )?;
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let names = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(names.iter().any(|name| name == column))
+}
 
 pub struct Database {
     conn: Connection,
+    namespace: String,
 }
 
 impl Database {
-    pub fn new(path: &str) -> Result<Self> {
+    /// Opens the database scoped to one Librus account.
+    ///
+    /// Ids are stored as `<namespace>:<id>` so items from different accounts never
+    /// collide; the single account without an id uses the `default` namespace.
+    pub fn new(path: &str, namespace: &str) -> Result<Self> {
         let conn = Connection::open(path)?;
-        let db = Self { conn };
+        let db = Self {
+            conn,
+            namespace: namespace.to_string(),
+        };
         db.init()?;
         Ok(db)
     }
 
-    fn init(&self) -> Result<()> {
-        self.conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS announcements (
-              id TEXT PRIMARY KEY,
-              title TEXT,
-              content TEXT,
-              date TEXT,
-              author TEXT,
-              fetched_at TEXT
-            );
+    /// Opens an in-memory copy of the database, so a dry run can fetch and store items
+    /// without touching the file.
+    pub fn scratch_copy(path: &str, namespace: &str) -> Result<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        if Path::new(path).exists() {
+            conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)
+                .with_context(|| format!("Failed to copy database {}", path))?;
+        }
+        let db = Self {
+            conn,
+            namespace: namespace.to_string(),
+        };
+        db.init()?;
+        Ok(db)
+    }
 
-            CREATE TABLE IF NOT EXISTS messages (
-              id TEXT PRIMARY KEY,
-              title TEXT,
-              content TEXT,
-              date TEXT,
-              sender TEXT,
-              fetched_at TEXT
-            );
+    fn id_prefix(&self) -> String {
+        if self.namespace.is_empty() {
+            format!("{}:", DEFAULT_NAMESPACE)
+        } else {
+            format!("{}:", self.namespace)
+        }
+    }
 
-            CREATE TABLE IF NOT EXISTS grades (
-              id TEXT PRIMARY KEY,
-              subject TEXT,
-              value TEXT,
-              info TEXT,
-              fetched_at TEXT
-            );
+    fn scoped_id(&self, id: &str) -> String {
+        format!("{}{}", self.id_prefix(), id)
+    }
 
-            CREATE TABLE IF NOT EXISTS events (
-              id TEXT PRIMARY KEY,
-              title TEXT,
-              day TEXT,
-              description TEXT,
-              fetched_at TEXT
-            );
+    /// Strips the namespace from a stored id, or `None` if it belongs to another account.
+    fn local_id(&self, id: String) -> Option<String> {
+        id.strip_prefix(&self.id_prefix()).map(str::to_string)
+    }
 
-            CREATE TABLE IF NOT EXISTS homework (
-              id TEXT PRIMARY KEY,
-              subject TEXT,
-              title TEXT,
-              type TEXT,
-              date_from TEXT,
-              date_to TEXT,
-              content TEXT,
-              teacher TEXT,
-              fetched_at TEXT
-            );
-            "#,
+    fn init(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, applied_at TEXT)",
         )?;
+        let current: usize = self.conn.query_row(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+            [],
+            |row| row.get(0),
+        )?;
+        if current > MIGRATIONS.len() {
+            bail!(
+                "Database schema version {} is newer than supported version {}; upgrade librus-notifications",
+                current,
+                MIGRATIONS.len()
+            );
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+            let version = index + 1;
+            tracing::info!(version, "Applying database migration");
+            migration(&tx).with_context(|| format!("Database migration {} failed", version))?;
+            tx.execute(
+                "INSERT INTO schema_version (version, applied_at) VALUES (?, ?)",
+                params![version, Utc::now().to_rfc3339()],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Ids that need no further processing; pending rows are left out so they are
+    /// fetched and reported again until delivery succeeds.
     fn existing_ids(&self, table: &str) -> Result<HashSet<String>> {
-        let mut stmt = self.conn.prepare(&format!("SELECT id FROM {}", table))?;
-        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
-        let mut ids = HashSet::new();
-        for id in rows {
-            if let Ok(id) = id {
-                ids.insert(id);
-            }
-        }
-        Ok(ids)
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT id FROM {} WHERE notify_state != ?", table))?;
+        let rows = stmt.query_map([NotifyState::Pending.as_str()], |row| {
+            row.get::<_, String>(0)
+        })?;
+        let ids = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(ids.into_iter().filter_map(|id| self.local_id(id)).collect())
     }
 
     pub fn existing_message_ids(&self) -> Result<HashSet<String>> {
@@ -91,61 +490,112 @@ impl Database {
         self.existing_ids("announcements")
     }
 
-    pub fn existing_grade_ids(&self) -> Result<HashSet<String>> {
-        self.existing_ids("grades")
+    /// Already processed grades keyed by id, to compare their content hash with Librus.
+    pub fn stored_grades(&self) -> Result<HashMap<String, GradeItem>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, subject, value, info, content_hash FROM grades WHERE notify_state != ?",
+        )?;
+        let rows = stmt.query_map([NotifyState::Pending.as_str()], |row| {
+            Ok(GradeItem {
+                id: row.get(0)?,
+                subject: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                value: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                info: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                content_hash: row.get(4)?,
+            })
+        })?;
+        let grades = rows
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter_map(|grade| {
+                let id = self.local_id(grade.id.clone())?;
+                Some((id.clone(), GradeItem { id, ..grade }))
+            })
+            .collect();
+        Ok(grades)
+    }
+
+    pub fn set_grade_hash(&self, id: &str, content_hash: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE grades SET content_hash = ? WHERE id = ?",
+            params![content_hash, self.scoped_id(id)],
+        )?;
+        Ok(())
     }
 
     pub fn existing_homework_ids(&self) -> Result<HashSet<String>> {
         self.existing_ids("homework")
     }
 
-    pub fn save_message(&self, message: &MessageItem) -> Result<()> {
+    pub fn existing_event_ids(&self) -> Result<HashSet<String>> {
+        self.existing_ids("events")
+    }
+
+    pub fn existing_attendance_ids(&self) -> Result<HashSet<String>> {
+        self.existing_ids("attendance")
+    }
+
+    pub fn save_message(&self, state: NotifyState, message: &MessageItem) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO messages (id, title, content, date, sender, fetched_at) VALUES (?, ?, ?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO messages (id, title, content, date, sender, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?, ?)",
             params![
-                message.id,
+                self.scoped_id(&message.id),
                 message.title,
                 message.body,
                 message.date,
                 message.user,
-                now
+                now,
+                state.as_str()
             ],
         )?;
         Ok(())
     }
 
-    pub fn save_announcement(&self, announcement: &AnnouncementItem) -> Result<()> {
+    pub fn save_announcement(
+        &self,
+        state: NotifyState,
+        announcement: &AnnouncementItem,
+    ) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO announcements (id, title, content, date, author, fetched_at) VALUES (?, ?, ?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO announcements (id, title, content, date, author, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?, ?)",
             params![
-                announcement.id,
+                self.scoped_id(&announcement.id),
                 announcement.title,
                 announcement.content,
                 announcement.date,
                 announcement.author,
-                now
+                now,
+                state.as_str()
             ],
         )?;
         Ok(())
     }
 
-    pub fn save_grade(&self, grade: &GradeItem) -> Result<()> {
+    pub fn save_grade(&self, state: NotifyState, grade: &GradeItem) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO grades (id, subject, value, info, fetched_at) VALUES (?, ?, ?, ?, ?)",
-            params![grade.id, grade.subject, grade.value, grade.info, now],
+            "INSERT OR REPLACE INTO grades (id, subject, value, info, content_hash, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                self.scoped_id(&grade.id),
+                grade.subject,
+                grade.value,
+                grade.info,
+                grade.content_hash,
+                now,
+                state.as_str()
+            ],
         )?;
         Ok(())
     }
 
-    pub fn save_homework(&self, homework: &HomeworkItem) -> Result<()> {
+    pub fn save_homework(&self, state: NotifyState, homework: &HomeworkItem) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO homework (id, subject, title, type, date_from, date_to, content, teacher, fetched_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO homework (id, subject, title, type, date_from, date_to, content, teacher, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
-                homework.id,
+                self.scoped_id(&homework.id),
                 homework.subject,
                 homework.title,
                 homework.kind,
@@ -153,9 +603,793 @@ impl Database {
                 homework.to,
                 homework.content,
                 homework.user,
-                now
+                now,
+                state.as_str()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_event(&self, state: NotifyState, event: &EventItem) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO events (id, title, day, description, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                self.scoped_id(&event.id),
+                event.title,
+                event.day,
+                event.description,
+                now,
+                state.as_str()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_attendance(&self, state: NotifyState, attendance: &AttendanceItem) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO attendance (id, date, lesson_no, subject, type, type_short, fetched_at, notify_state) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                self.scoped_id(&attendance.id),
+                attendance.date,
+                attendance.lesson_no,
+                attendance.subject,
+                attendance.kind,
+                attendance.short,
+                now,
+                state.as_str()
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn query_homework(&self, filter: &str, params: impl Params) -> Result<Vec<HomeworkItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, subject, title, type, date_from, date_to, content, teacher FROM homework h WHERE {} ORDER BY date_to, subject",
+            filter
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(HomeworkItem {
+                id: row.get(0)?,
+                subject: row.get(1)?,
+                title: row.get(2)?,
+                kind: row.get(3)?,
+                from: row.get(4)?,
+                to: row.get(5)?,
+                content: row.get(6)?,
+                user: row.get(7)?,
+            })
+        })?;
+        let items = rows
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter_map(|item| {
+                let id = self.local_id(item.id.clone())?;
+                Some(HomeworkItem { id, ..item })
+            })
+            .collect();
+        Ok(items)
+    }
+
+    fn query_events(&self, filter: &str, params: impl Params) -> Result<Vec<EventItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, title, day, description FROM events e WHERE {} ORDER BY day, title",
+            filter
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(EventItem {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                day: row.get(2)?,
+                description: row.get(3)?,
+            })
+        })?;
+        let items = rows
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter_map(|item| {
+                let id = self.local_id(item.id.clone())?;
+                Some(EventItem { id, ..item })
+            })
+            .collect();
+        Ok(items)
+    }
+
+    /// Reported homework due on `due` (`YYYY-MM-DD`) that was not reminded about yet.
+    pub fn due_homework(&self, due: &str) -> Result<Vec<HomeworkItem>> {
+        self.query_homework(
+            "substr(date_to, 1, 10) = ?1 AND notify_state = ?2 AND NOT EXISTS (SELECT 1 FROM reminders r WHERE r.kind = 'homework' AND r.id = h.id AND r.due = ?1)",
+            params![due, NotifyState::Notified.as_str()],
+        )
+    }
+
+    /// Reported calendar events on `due` (`YYYY-MM-DD`) that were not reminded about yet.
+    pub fn due_events(&self, due: &str) -> Result<Vec<EventItem>> {
+        self.query_events(
+            "substr(day, 1, 10) = ?1 AND notify_state = ?2 AND NOT EXISTS (SELECT 1 FROM reminders r WHERE r.kind = 'events' AND r.id = e.id AND r.due = ?1)",
+            params![due, NotifyState::Notified.as_str()],
+        )
+    }
+
+    /// All stored homework of this account, for the calendar export.
+    pub fn calendar_homework(&self) -> Result<Vec<HomeworkItem>> {
+        self.query_homework("date_to IS NOT NULL", [])
+    }
+
+    /// All stored calendar events of this account, including past ones.
+    pub fn calendar_events(&self) -> Result<Vec<EventItem>> {
+        self.query_events("day IS NOT NULL", [])
+    }
+
+    /// Marks every item carried by a delivered digest as notified, stores its action
+    /// items as tasks, records its reminders as sent and moves the timetable snapshot
+    /// forward.
+    ///
+    /// New homework and events already due within the reminder window count as
+    /// reminded, so they are not announced twice in a row.
+    pub fn mark_notified(&self, digest: &Digest) -> Result<()> {
+        let ids: [(&str, Vec<&str>); 6] = [
+            (
+                "announcements",
+                digest.announcements.iter().map(|i| i.id.as_str()).collect(),
+            ),
+            (
+                "messages",
+                digest.messages.iter().map(|i| i.id.as_str()).collect(),
+            ),
+            (
+                "grades",
+                digest.grades.iter().map(|i| i.id.as_str()).collect(),
+            ),
+            (
+                "events",
+                digest.events.iter().map(|i| i.id.as_str()).collect(),
+            ),
+            (
+                "homework",
+                digest.homework.iter().map(|i| i.id.as_str()).collect(),
+            ),
+            (
+                "attendance",
+                digest.attendance.iter().map(|i| i.id.as_str()).collect(),
+            ),
+        ];
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (table, table_ids) in ids {
+            for id in table_ids {
+                tx.execute(
+                    &format!("UPDATE {} SET notify_state = ? WHERE id = ?", table),
+                    params![NotifyState::Notified.as_str(), self.scoped_id(id)],
+                )?;
+            }
+        }
+        // Edited grades keep their row; only the stored content moves forward
+        for change in &digest.grade_changes {
+            tx.execute(
+                "UPDATE grades SET value = ?, info = ?, content_hash = ?, fetched_at = ? WHERE id = ?",
+                params![
+                    change.grade.value,
+                    change.grade.info,
+                    change.grade.content_hash,
+                    Utc::now().to_rfc3339(),
+                    self.scoped_id(&change.grade.id)
+                ],
+            )?;
+        }
+
+        for (source, analysis) in digest.analyses_by_source() {
+            let items = analysis.iter().flat_map(|analysis| &analysis.action_items);
+            for item in items {
+                tx.execute(
+                    "INSERT OR IGNORE INTO tasks (account, key, kind, due, amount_pln, description, source, source_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![
+                        self.namespace,
+                        item.key(source),
+                        item.kind.as_str(),
+                        item.due,
+                        item.amount_pln,
+                        item.description,
+                        source,
+                        item.source_id,
+                        Utc::now().to_rfc3339()
+                    ],
+                )?;
+            }
+        }
+
+        let reminders = &digest.reminders;
+        let homework = digest.homework.iter().chain(&reminders.homework);
+        let events = digest.events.iter().chain(&reminders.events);
+        let due_dates = homework
+            .map(|item| ("homework", &item.id, &item.to))
+            .chain(events.map(|item| ("events", &item.id, &item.day)));
+        for (kind, id, date) in due_dates {
+            let date = date.get(..10).unwrap_or(date);
+            if reminders.due.is_empty() || date > reminders.due.as_str() {
+                continue;
+            }
+            tx.execute(
+                "INSERT OR IGNORE INTO reminders (kind, id, due, sent_at) VALUES (?, ?, ?, ?)",
+                params![kind, self.scoped_id(id), date, Utc::now().to_rfc3339()],
+            )?;
+        }
+        self.write_timetable_snapshot(&tx, &digest.timetable_snapshot)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stored items of this account, most recently fetched first.
+    pub fn stored_items(&self, kind: ItemKind, limit: usize) -> Result<Vec<StoredItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, notify_state, fetched_at, {} FROM {} ORDER BY fetched_at DESC",
+            kind.summary_sql(),
+            kind.table()
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok(StoredItem {
+                id: row.get(0)?,
+                notify_state: row.get(1)?,
+                fetched_at: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                summary: row.get(3)?,
+            })
+        })?;
+        let items = rows
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter_map(|item| {
+                let id = self.local_id(item.id.clone())?;
+                Some(StoredItem { id, ..item })
+            })
+            .take(limit)
+            .collect();
+        Ok(items)
+    }
+
+    /// Deletes stored items of `kind`, or only the one with `id`, so the next run
+    /// reports them as new. Returns the number of removed rows.
+    pub fn forget(&self, kind: ItemKind, id: Option<&str>) -> Result<usize> {
+        let ids: Vec<String> = match id {
+            Some(id) => vec![id.to_string()],
+            None => self
+                .stored_items(kind, usize::MAX)?
+                .into_iter()
+                .map(|item| item.id)
+                .collect(),
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut removed = 0;
+        for id in ids {
+            removed += tx.execute(
+                &format!("DELETE FROM {} WHERE id = ?", kind.table()),
+                [self.scoped_id(&id)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    pub fn queue_digest(&self, digest: &Digest, now: NaiveDateTime) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO digest_queue (account, queued_at, payload) VALUES (?, ?, ?)",
+            params![
+                self.namespace,
+                now.format(QUEUE_TIME_FORMAT).to_string(),
+                serde_json::to_string(digest)?
             ],
         )?;
         Ok(())
     }
+
+    /// Queued digests of this account, oldest first.
+    pub fn queued_digests(&self) -> Result<Vec<QueuedDigest>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, queued_at, payload FROM digest_queue WHERE account = ? ORDER BY id",
+        )?;
+        let rows = stmt.query_map([&self.namespace], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut queued = Vec::new();
+        for row in rows {
+            let (id, queued_at, payload) = row?;
+            queued.push(QueuedDigest {
+                id,
+                queued_at: NaiveDateTime::parse_from_str(&queued_at, QUEUE_TIME_FORMAT)?,
+                digest: serde_json::from_str(&payload)
+                    .with_context(|| format!("Invalid queued digest {}", id))?,
+            });
+        }
+        Ok(queued)
+    }
+
+    /// Replaces queued digests with what a partly failed flush left of them, keeping
+    /// their queue time; the flush time is not recorded, so the next run retries.
+    pub fn requeue(&self, ids: &[i64], digests: &[Digest], queued_at: NaiveDateTime) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for id in ids {
+            tx.execute(
+                "DELETE FROM digest_queue WHERE id = ? AND account = ?",
+                params![id, self.namespace],
+            )?;
+        }
+        for digest in digests {
+            tx.execute(
+                "INSERT INTO digest_queue (account, queued_at, payload) VALUES (?, ?, ?)",
+                params![
+                    self.namespace,
+                    queued_at.format(QUEUE_TIME_FORMAT).to_string(),
+                    serde_json::to_string(digest)?
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// When the queue of this account was last sent.
+    pub fn last_flush(&self) -> Result<Option<NaiveDateTime>> {
+        let flushed_at: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT flushed_at FROM digest_flushes WHERE account = ?",
+                [&self.namespace],
+                |row| row.get(0),
+            )
+            .optional()?;
+        flushed_at
+            .map(|at| Ok(NaiveDateTime::parse_from_str(&at, QUEUE_TIME_FORMAT)?))
+            .transpose()
+    }
+
+    /// Removes the sent digests and records the time they went out.
+    pub fn finish_flush(&self, ids: &[i64], now: NaiveDateTime) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for id in ids {
+            tx.execute(
+                "DELETE FROM digest_queue WHERE id = ? AND account = ?",
+                params![id, self.namespace],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO digest_flushes (account, flushed_at) VALUES (?, ?)",
+            params![self.namespace, now.format(QUEUE_TIME_FORMAT).to_string()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Tasks of this account, open ones first by due date; done tasks only with `include_done`.
+    pub fn tasks(&self, include_done: bool) -> Result<Vec<Task>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, due, amount_pln, description, source, source_id, done_at FROM tasks WHERE account = ? AND (? OR done_at IS NULL) ORDER BY done_at IS NOT NULL, due IS NULL, due, id",
+        )?;
+        let rows = stmt.query_map(params![self.namespace, include_done], |row| {
+            Ok(Task {
+                id: row.get(0)?,
+                item: ActionItem {
+                    kind: ActionKind::parse(&row.get::<_, String>(1)?),
+                    due: row.get(2)?,
+                    amount_pln: row.get(3)?,
+                    description: row.get(4)?,
+                    source_id: row.get(6)?,
+                },
+                source: row.get(5)?,
+                done_at: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Marks an open task of this account as done; false when there is no such task.
+    pub fn complete_task(&self, id: i64) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE tasks SET done_at = ? WHERE id = ? AND account = ? AND done_at IS NULL",
+            params![Utc::now().to_rfc3339(), id, self.namespace],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn timetable_snapshot(
+        &self,
+        date_from: &str,
+        date_to: &str,
+    ) -> Result<Vec<TimetableLesson>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date, lesson_no, hour_from, hour_to, subject, teacher, canceled, substitution FROM timetable WHERE account = ? AND date >= ? AND date <= ? ORDER BY date, lesson_no",
+        )?;
+        let rows = stmt.query_map(params![self.namespace, date_from, date_to], |row| {
+            Ok(TimetableLesson {
+                date: row.get(0)?,
+                lesson_no: row.get(1)?,
+                hour_from: row.get(2)?,
+                hour_to: row.get(3)?,
+                subject: row.get(4)?,
+                teacher: row.get(5)?,
+                canceled: row.get(6)?,
+                substitution: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn replace_timetable_snapshot(&self, snapshot: &TimetableSnapshot) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.write_timetable_snapshot(&tx, snapshot)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn write_timetable_snapshot(
+        &self,
+        conn: &Connection,
+        snapshot: &TimetableSnapshot,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        for date in &snapshot.dates {
+            conn.execute(
+                "DELETE FROM timetable WHERE account = ? AND date = ?",
+                params![self.namespace, date],
+            )?;
+        }
+        for lesson in &snapshot.lessons {
+            conn.execute(
+                "INSERT OR REPLACE INTO timetable (account, date, lesson_no, hour_from, hour_to, subject, teacher, canceled, substitution, fetched_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    self.namespace,
+                    lesson.date,
+                    lesson.lesson_no,
+                    lesson.hour_from,
+                    lesson.hour_to,
+                    lesson.subject,
+                    lesson.teacher,
+                    lesson.canceled,
+                    lesson.substitution,
+                    now
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Analysis, GradeChange, Reminders, Urgency};
+
+    fn grade(id: &str) -> GradeItem {
+        GradeItem {
+            id: id.to_string(),
+            subject: "Matematyka".to_string(),
+            value: "5".to_string(),
+            info: String::new(),
+            content_hash: "abc".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pending_items_are_retried_until_notified() {
+        let db = Database::new(":memory:", "emilka").unwrap();
+        db.save_grade(NotifyState::Pending, &grade("1")).unwrap();
+        db.save_grade(NotifyState::Skipped, &grade("2")).unwrap();
+
+        let stored = db.stored_grades().unwrap();
+        assert!(!stored.contains_key("1"));
+        assert!(stored.contains_key("2"));
+
+        // A retried run stores the same pending row again
+        db.save_grade(NotifyState::Pending, &grade("1")).unwrap();
+
+        let digest = Digest {
+            grades: vec![grade("1")],
+            ..Digest::default()
+        };
+        db.mark_notified(&digest).unwrap();
+        assert!(db.stored_grades().unwrap().contains_key("1"));
+    }
+
+    #[test]
+    fn test_legacy_rows_count_as_notified() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE grades (id TEXT PRIMARY KEY, subject TEXT, value TEXT, info TEXT, fetched_at TEXT);
+             INSERT INTO grades (id) VALUES ('7');",
+        )
+        .unwrap();
+        let db = Database {
+            conn,
+            namespace: String::new(),
+        };
+        db.init().unwrap();
+        assert_eq!(db.stored_grades().unwrap()["7"].content_hash, "");
+    }
+
+    #[test]
+    fn test_default_namespace_keeps_ids_with_colons() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE grades (id TEXT PRIMARY KEY, subject TEXT, value TEXT, info TEXT, fetched_at TEXT);
+             INSERT INTO grades (id) VALUES ('7');
+             INSERT INTO grades (id) VALUES ('emilka:8');
+             CREATE TABLE timetable (date TEXT, lesson_no TEXT, hour_from TEXT, hour_to TEXT, subject TEXT, teacher TEXT, canceled INTEGER, substitution INTEGER, fetched_at TEXT, PRIMARY KEY (date, lesson_no, subject));
+             INSERT INTO timetable (date, lesson_no, subject) VALUES ('2025-03-10', '1', 'Polski');",
+        )
+        .unwrap();
+        let db = Database {
+            conn,
+            namespace: String::new(),
+        };
+        db.init().unwrap();
+        db.save_grade(NotifyState::Notified, &grade("9:1")).unwrap();
+
+        let stored = db.stored_grades().unwrap();
+        assert!(stored.contains_key("7") && stored.contains_key("9:1"));
+        assert!(!stored.contains_key("emilka:8"));
+        let emilka = Database {
+            conn: db.conn,
+            namespace: "emilka".to_string(),
+        };
+        assert!(emilka.stored_grades().unwrap().contains_key("8"));
+
+        let account: String = emilka
+            .conn
+            .query_row("SELECT account FROM timetable", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(account, "");
+    }
+
+    #[test]
+    fn test_notified_grade_change_updates_stored_content() {
+        let db = Database::new(":memory:", "").unwrap();
+        db.save_grade(NotifyState::Notified, &grade("1")).unwrap();
+
+        let mut edited = grade("1");
+        edited.value = "5+".to_string();
+        edited.content_hash = "def".to_string();
+        let digest = Digest {
+            grade_changes: vec![GradeChange {
+                grade: edited,
+                previous_value: "5".to_string(),
+                previous_info: String::new(),
+            }],
+            ..Digest::default()
+        };
+        db.mark_notified(&digest).unwrap();
+
+        let stored = &db.stored_grades().unwrap()["1"];
+        assert_eq!(stored.value, "5+");
+        assert_eq!(stored.content_hash, "def");
+    }
+
+    #[test]
+    fn test_timetable_snapshot_moves_forward_when_notified() {
+        let db = Database::new(":memory:", "emilka").unwrap();
+        let lesson = TimetableLesson {
+            date: "2025-03-10".to_string(),
+            lesson_no: "1".to_string(),
+            hour_from: "08:00".to_string(),
+            hour_to: "08:45".to_string(),
+            subject: "Polski".to_string(),
+            teacher: "Anna Nowak".to_string(),
+            canceled: true,
+            substitution: false,
+        };
+        let digest = Digest {
+            timetable_snapshot: TimetableSnapshot {
+                dates: vec!["2025-03-10".to_string()],
+                lessons: vec![lesson.clone()],
+            },
+            ..Digest::default()
+        };
+        assert!(db
+            .timetable_snapshot("2025-03-10", "2025-03-10")
+            .unwrap()
+            .is_empty());
+        db.mark_notified(&digest).unwrap();
+        assert_eq!(
+            db.timetable_snapshot("2025-03-10", "2025-03-10").unwrap(),
+            vec![lesson]
+        );
+    }
+
+    #[test]
+    fn test_migrations_are_applied_once() {
+        let db = Database::new(":memory:", "").unwrap();
+        db.init().unwrap();
+        let versions: usize = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(versions, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let db = Database::new(":memory:", "").unwrap();
+        db.conn
+            .execute(
+                "INSERT INTO schema_version (version) VALUES (?)",
+                [MIGRATIONS.len() + 1],
+            )
+            .unwrap();
+        let err = db.init().unwrap_err();
+        assert!(err.to_string().contains("newer than supported"));
+    }
+
+    #[test]
+    fn test_forget_only_touches_own_account() {
+        let path = std::env::temp_dir().join(format!("librus-forget-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let emilka = Database::new(path, "emilka").unwrap();
+        let jas = Database::new(path, "jas").unwrap();
+        emilka
+            .save_grade(NotifyState::Notified, &grade("1"))
+            .unwrap();
+        emilka
+            .save_grade(NotifyState::Notified, &grade("2"))
+            .unwrap();
+        jas.save_grade(NotifyState::Notified, &grade("1")).unwrap();
+
+        let shown = emilka.stored_items(ItemKind::Grades, 10).unwrap();
+        assert_eq!(shown.len(), 2);
+        assert_eq!(shown[0].summary, "Matematyka: 5");
+
+        assert_eq!(emilka.forget(ItemKind::Grades, Some("2")).unwrap(), 1);
+        assert_eq!(emilka.forget(ItemKind::Grades, None).unwrap(), 1);
+        assert!(emilka.stored_grades().unwrap().is_empty());
+        assert!(jas.stored_grades().unwrap().contains_key("1"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reminders_are_sent_once_per_due_date() {
+        let db = Database::new(":memory:", "emilka").unwrap();
+        let homework = |id: &str, to: &str| HomeworkItem {
+            id: id.to_string(),
+            subject: "Polski".to_string(),
+            title: "Czytanka".to_string(),
+            kind: "Zadanie domowe".to_string(),
+            from: "2025-03-10".to_string(),
+            to: to.to_string(),
+            content: "str. 12".to_string(),
+            user: "Anna Nowak".to_string(),
+        };
+        db.save_homework(NotifyState::Notified, &homework("1", "2025-03-14"))
+            .unwrap();
+        db.save_homework(NotifyState::Pending, &homework("2", "2025-03-14"))
+            .unwrap();
+        db.save_homework(NotifyState::Notified, &homework("3", "2025-03-20"))
+            .unwrap();
+
+        let due = db.due_homework("2025-03-14").unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "1");
+
+        // Homework 2 is new and due within the window, so it counts as reminded
+        let digest = Digest {
+            homework: vec![homework("2", "2025-03-14"), homework("3", "2025-03-20")],
+            reminders: Reminders {
+                due: "2025-03-14".to_string(),
+                homework: due,
+                ..Reminders::default()
+            },
+            ..Digest::default()
+        };
+        db.mark_notified(&digest).unwrap();
+        assert!(db.due_homework("2025-03-14").unwrap().is_empty());
+        assert_eq!(db.due_homework("2025-03-20").unwrap().len(), 1);
+
+        // A moved deadline is reminded again
+        db.save_homework(NotifyState::Notified, &homework("1", "2025-03-17"))
+            .unwrap();
+        assert_eq!(db.due_homework("2025-03-17").unwrap().len(), 1);
+        assert_eq!(db.calendar_homework().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_action_items_are_stored_once_and_completed() {
+        let path = std::env::temp_dir().join(format!("librus-tasks-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let emilka = Database::new(path, "emilka").unwrap();
+        let jas = Database::new(path, "jas").unwrap();
+        let digest = Digest {
+            messages_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Wycieczka".to_string(),
+                key_points: Vec::new(),
+                action_items: vec![ActionItem {
+                    kind: ActionKind::Payment,
+                    due: Some("2025-03-14".to_string()),
+                    amount_pln: Some(20.0),
+                    description: "Wpłata na wycieczkę".to_string(),
+                    source_id: Some("42".to_string()),
+                }],
+            }),
+            ..Digest::default()
+        };
+        emilka.mark_notified(&digest).unwrap();
+        // Analysing the same message again rephrases the description
+        let mut rephrased = digest.clone();
+        rephrased.messages_analysis.as_mut().unwrap().action_items[0].description =
+            "Zapłacić za wycieczkę".to_string();
+        emilka.mark_notified(&rephrased).unwrap();
+
+        let tasks = emilka.tasks(false).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].source, "messages");
+        assert_eq!(tasks[0].item.kind, ActionKind::Payment);
+        assert!(jas.tasks(true).unwrap().is_empty());
+
+        assert!(!jas.complete_task(tasks[0].id).unwrap());
+        assert!(emilka.complete_task(tasks[0].id).unwrap());
+        assert!(emilka.tasks(false).unwrap().is_empty());
+        assert!(emilka.tasks(true).unwrap()[0].done_at.is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_digest_queue_round_trip() {
+        let db = Database::new(":memory:", "emilka").unwrap();
+        let now = NaiveDateTime::parse_from_str("2025-03-10 12:00:00", QUEUE_TIME_FORMAT).unwrap();
+        let digest = Digest {
+            student: "Emilka".to_string(),
+            grades: vec![grade("1")],
+            grades_analysis: Some(Analysis {
+                urgency: Urgency::Niepilne,
+                summary: "Piątka".to_string(),
+                key_points: vec!["⭐ **5**".to_string()],
+                action_items: Vec::new(),
+            }),
+            ..Digest::default()
+        };
+        db.queue_digest(&digest, now).unwrap();
+        assert_eq!(db.last_flush().unwrap(), None);
+
+        let queued = db.queued_digests().unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].queued_at, now);
+        assert_eq!(queued[0].digest.grades[0].id, "1");
+        let analysis = queued[0].digest.grades_analysis.as_ref().unwrap();
+        assert_eq!(analysis.urgency, Urgency::Niepilne);
+
+        let later = now + chrono::Duration::hours(6);
+        let mut rest = digest.clone();
+        rest.grades_analysis = None;
+        db.requeue(&[queued[0].id], &[rest], now).unwrap();
+        let queued = db.queued_digests().unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].queued_at, now);
+        assert!(queued[0].digest.grades_analysis.is_none());
+        assert_eq!(db.last_flush().unwrap(), None);
+
+        db.finish_flush(&[queued[0].id], later).unwrap();
+        assert!(db.queued_digests().unwrap().is_empty());
+        assert_eq!(db.last_flush().unwrap(), Some(later));
+    }
+
+    #[test]
+    fn test_scratch_copy_leaves_file_untouched() {
+        let path = std::env::temp_dir().join(format!("librus-scratch-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        Database::new(path, "")
+            .unwrap()
+            .save_grade(NotifyState::Notified, &grade("1"))
+            .unwrap();
+
+        let scratch = Database::scratch_copy(path, "").unwrap();
+        assert!(scratch.stored_grades().unwrap().contains_key("1"));
+        scratch
+            .save_grade(NotifyState::Notified, &grade("2"))
+            .unwrap();
+
+        let stored = Database::new(path, "").unwrap().stored_grades().unwrap();
+        assert!(!stored.contains_key("2"));
+        std::fs::remove_file(path).unwrap();
+    }
 }
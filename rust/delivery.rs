@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::config::{DigestConfig, DigestMode};
+use crate::email::section_urgency;
+use crate::models::{Analysis, Digest};
+use crate::routing::{retain_sections, SectionKind};
+
+/// Decides when digest sections go out.
+///
+/// Urgent sections are always sent right away. The rest is sent immediately in
+/// `instant` mode, or queued and sent at the daily or weekly digest time. During quiet
+/// hours only urgent sections are sent and the queue waits until they end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    mode: DigestMode,
+    time: NaiveTime,
+    weekday: Weekday,
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Delivery {
+    pub fn from_config(config: &DigestConfig) -> Result<Self> {
+        let quiet_hours = if config.quiet_hours.trim().is_empty() {
+            None
+        } else {
+            let (start, end) = config
+                .quiet_hours
+                .split_once('-')
+                .and_then(|(start, end)| Some((parse_time(start).ok()?, parse_time(end).ok()?)))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid quiet hours '{}', expected HH:MM-HH:MM",
+                        config.quiet_hours
+                    )
+                })?;
+            Some((start, end))
+        };
+        Ok(Self {
+            mode: config.mode,
+            time: parse_time(&config.time)?,
+            weekday: config
+                .weekday
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid weekday '{}', expected e.g. sun", config.weekday))?,
+            quiet_hours,
+        })
+    }
+
+    pub fn is_quiet(&self, now: NaiveDateTime) -> bool {
+        let Some((start, end)) = self.quiet_hours else {
+            return false;
+        };
+        let time = now.time();
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+
+    /// Splits a digest into the sections to send now and the ones to queue.
+    pub fn split(&self, digest: &Digest, now: NaiveDateTime) -> (Digest, Digest) {
+        let hold = self.mode != DigestMode::Instant || self.is_quiet(now);
+        let send_now = |section: SectionKind| {
+            !hold || section_urgency(digest, section).is_some_and(|urgency| urgency.is_instant())
+        };
+        (
+            retain_sections(digest, send_now),
+            retain_sections(digest, |section| !send_now(section)),
+        )
+    }
+
+    /// The latest digest time not after `now`; the queue is sent when it was last sent
+    /// before that time. `None` during quiet hours.
+    pub fn last_slot(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if self.is_quiet(now) {
+            return None;
+        }
+        let slot = |days_back: u32, period: i64| {
+            let slot = (now.date() - Duration::days(days_back.into())).and_time(self.time);
+            if slot <= now {
+                slot
+            } else {
+                slot - Duration::days(period)
+            }
+        };
+        Some(match self.mode {
+            DigestMode::Instant => now,
+            DigestMode::Daily => slot(0, 1),
+            DigestMode::Weekly => {
+                let days_back = (now.weekday().num_days_from_monday() + 7
+                    - self.weekday.num_days_from_monday())
+                    % 7;
+                slot(days_back, 7)
+            }
+        })
+    }
+}
+
+/// True when the queue, last sent at `last_flush`, is due at the digest time `slot`.
+///
+/// A queue never sent before waits for the first digest time since its oldest entry,
+/// including one queued right at that time.
+pub fn flush_due(
+    last_flush: Option<NaiveDateTime>,
+    queued_at: NaiveDateTime,
+    slot: NaiveDateTime,
+) -> bool {
+    match last_flush {
+        Some(last_flush) => last_flush < slot,
+        None => queued_at <= slot,
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .with_context(|| format!("Invalid time '{}', expected HH:MM", time))
+}
+
+/// Combines queued digests of one account, oldest first.
+///
+/// Reminders are for one due date each, so a digest with reminders for another date
+/// than the ones collected so far starts a new digest.
+pub fn merge(digests: Vec<Digest>) -> Vec<Digest> {
+    let mut merged: Vec<Digest> = Vec::new();
+    for digest in digests {
+        let target = match merged.last_mut() {
+            Some(target)
+                if target.reminders.is_empty()
+                    || digest.reminders.is_empty()
+                    || target.reminders.due == digest.reminders.due =>
+            {
+                target
+            }
+            _ => {
+                merged.push(Digest::default());
+                merged.last_mut().unwrap()
+            }
+        };
+        target.account = digest.account;
+        target.student = digest.student;
+        merge_analysis(
+            &mut target.announcements_analysis,
+            digest.announcements_analysis,
+        );
+        merge_analysis(&mut target.messages_analysis, digest.messages_analysis);
+        merge_analysis(&mut target.grades_analysis, digest.grades_analysis);
+        merge_analysis(&mut target.homework_analysis, digest.homework_analysis);
+        merge_analysis(&mut target.events_analysis, digest.events_analysis);
+        target.announcements.extend(digest.announcements);
+        target.messages.extend(digest.messages);
+        target.grades.extend(digest.grades);
+        target.grade_changes.extend(digest.grade_changes);
+        target.homework.extend(digest.homework);
+        target.events.extend(digest.events);
+        target.attendance.extend(digest.attendance);
+        target.timetable_changes.extend(digest.timetable_changes);
+        if !digest.reminders.is_empty() {
+            let reminders = &mut target.reminders;
+            reminders.due = digest.reminders.due;
+            reminders.label = digest.reminders.label;
+            reminders.homework.extend(digest.reminders.homework);
+            reminders.events.extend(digest.reminders.events);
+        }
+    }
+    merged
+}
+
+fn merge_analysis(target: &mut Option<Analysis>, analysis: Option<Analysis>) {
+    let Some(analysis) = analysis else {
+        return;
+    };
+    match target {
+        None => *target = Some(analysis),
+        Some(target) => {
+            target.urgency = target.urgency.max(analysis.urgency);
+            target.summary = format!("{}\n{}", target.summary, analysis.summary);
+            target.key_points.extend(analysis.key_points);
+            target.action_items.extend(analysis.action_items);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::models::{HomeworkItem, Reminders, Urgency};
+
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 3, day)
+            .unwrap()
+            .and_time(parse_time(time).unwrap())
+    }
+
+    fn delivery(mode: DigestMode, quiet_hours: &str) -> Delivery {
+        Delivery::from_config(&DigestConfig {
+            mode,
+            quiet_hours: quiet_hours.to_string(),
+            ..DigestConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours_and_slots() {
+        // 2025-03-09 is a Sunday
+        let quiet = delivery(DigestMode::Instant, "22:00-07:00");
+        assert!(quiet.is_quiet(at(10, "23:30")));
+        assert!(quiet.is_quiet(at(10, "06:59")));
+        assert!(!quiet.is_quiet(at(10, "07:00")));
+        assert_eq!(quiet.last_slot(at(10, "23:30")), None);
+        assert_eq!(quiet.last_slot(at(10, "12:00")), Some(at(10, "12:00")));
+
+        let daily = delivery(DigestMode::Daily, "");
+        assert_eq!(daily.last_slot(at(10, "19:00")), Some(at(10, "18:00")));
+        assert_eq!(daily.last_slot(at(10, "07:00")), Some(at(9, "18:00")));
+
+        let weekly = delivery(DigestMode::Weekly, "");
+        assert_eq!(weekly.last_slot(at(12, "07:00")), Some(at(9, "18:00")));
+        assert_eq!(weekly.last_slot(at(9, "17:00")), Some(at(2, "18:00")));
+        assert_eq!(weekly.last_slot(at(9, "18:00")), Some(at(9, "18:00")));
+
+        assert!(Delivery::from_config(&DigestConfig {
+            weekday: "niedziela".to_string(),
+            ..DigestConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_flush_due_at_the_slot() {
+        let slot = at(10, "18:00");
+        // First flush of a queue started right at the digest time
+        assert!(flush_due(None, at(10, "18:00"), slot));
+        assert!(flush_due(None, at(10, "09:00"), slot));
+        assert!(!flush_due(None, at(10, "18:30"), slot));
+        assert!(!flush_due(Some(at(10, "18:00")), at(10, "09:00"), slot));
+        assert!(flush_due(Some(at(9, "18:00")), at(10, "18:00"), slot));
+    }
+
+    fn homework(id: &str) -> HomeworkItem {
+        HomeworkItem {
+            id: id.to_string(),
+            subject: "Polski".to_string(),
+            title: "Czytanka".to_string(),
+            kind: "Zadanie domowe".to_string(),
+            from: "2025-03-10".to_string(),
+            to: "2025-03-20".to_string(),
+            content: String::new(),
+            user: "Anna Nowak".to_string(),
+        }
+    }
+
+    fn digest(id: &str, urgency: Urgency) -> Digest {
+        Digest {
+            student: "Emilka".to_string(),
+            homework_analysis: Some(Analysis {
+                urgency,
+                summary: format!("Zadanie {}", id),
+                key_points: vec![format!("📝 {}", id)],
+                action_items: Vec::new(),
+            }),
+            homework: vec![homework(id)],
+            ..Digest::default()
+        }
+    }
+
+    #[test]
+    fn test_split_holds_back_all_but_urgent_sections() {
+        let mut mixed = digest("1", Urgency::Normalne);
+        mixed.messages_analysis = digest("2", Urgency::Pilne).homework_analysis;
+
+        let (now, later) = delivery(DigestMode::Daily, "").split(&mixed, at(10, "12:00"));
+        assert!(now.homework.is_empty() && now.messages_analysis.is_some());
+        assert_eq!(later.homework.len(), 1);
+        assert!(later.messages_analysis.is_none());
+
+        let instant = delivery(DigestMode::Instant, "22:00-07:00");
+        let (now, later) = instant.split(&mixed, at(10, "12:00"));
+        assert_eq!(now.homework.len(), 1);
+        assert!(later.is_empty());
+        let (now, _) = instant.split(&mixed, at(10, "23:00"));
+        assert!(now.homework.is_empty());
+    }
+
+    #[test]
+    fn test_merge_combines_queued_digests() {
+        let mut merged = merge(vec![
+            digest("1", Urgency::Niepilne),
+            digest("2", Urgency::Normalne),
+        ]);
+        assert_eq!(merged.len(), 1);
+        let merged = merged.remove(0);
+        assert_eq!(merged.student, "Emilka");
+        assert_eq!(merged.homework.len(), 2);
+        let analysis = merged.homework_analysis.unwrap();
+        assert_eq!(analysis.urgency, Urgency::Normalne);
+        assert_eq!(analysis.summary, "Zadanie 1\nZadanie 2");
+        assert_eq!(analysis.key_points, vec!["📝 1", "📝 2"]);
+    }
+
+    #[test]
+    fn test_merge_keeps_reminders_for_other_dates_apart() {
+        let reminder = |id: &str, due: &str| {
+            let mut digest = digest(id, Urgency::Normalne);
+            digest.reminders = Reminders {
+                due: due.to_string(),
+                label: format!("Na {}", due),
+                homework: vec![homework(id)],
+                events: Vec::new(),
+            };
+            digest
+        };
+        let merged = merge(vec![
+            reminder("1", "2025-03-11"),
+            reminder("2", "2025-03-11"),
+            digest("3", Urgency::Normalne),
+            reminder("4", "2025-03-12"),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].reminders.due, "2025-03-11");
+        assert_eq!(merged[0].reminders.homework.len(), 2);
+        assert_eq!(merged[0].homework.len(), 3);
+        assert_eq!(merged[1].reminders.due, "2025-03-12");
+        assert_eq!(merged[1].reminders.label, "Na 2025-03-12");
+        assert_eq!(merged[1].homework.len(), 1);
+    }
+}
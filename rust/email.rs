@@ -1,587 +1,408 @@
-use std::env;
+use std::path::Path;
 
 use anyhow::{anyhow, Result};
-use chrono::Local;
-use lettre::message::SinglePart;
+use async_trait::async_trait;
+use chrono::{Duration, Local};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, MultiPart};
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
-use once_cell::sync::Lazy;
-use regex::Regex;
-
-use crate::models::{Analysis, AnnouncementItem, GradeItem, HomeworkItem, MessageItem};
-
-static BOLD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*\*([^*]+)\*\*").unwrap());
-static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
-
-fn should_send_email() -> bool {
-    env::var("SEND_EMAIL")
-        .map(|val| val == "true")
-        .unwrap_or(false)
+use serde::Serialize;
+
+use crate::config::EmailConfig;
+use crate::ics::{self, CalendarEvent};
+use crate::models::{
+    Analysis, AnnouncementItem, AttendanceItem, Digest, EventItem, GradeItem, HomeworkItem,
+    MessageItem, Reminders, TimetableChange, Urgency,
+};
+use crate::notifier::Notifier;
+use crate::routing::SectionKind;
+use crate::template;
+
+const ICS_FILENAME: &str = "librus.ics";
+
+/// Anything happening by tomorrow is urgent.
+fn due_urgency<'a>(mut dates: impl Iterator<Item = &'a str>) -> Urgency {
+    let tomorrow = (Local::now().date_naive() + Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    if dates.any(|date| date <= tomorrow.as_str()) {
+        Urgency::Pilne
+    } else {
+        Urgency::Normalne
+    }
 }
 
-fn generate_urgency_badge(urgency: &str) -> String {
-    let class = match urgency {
-        "PILNE" => "badge-urgent",
-        "NORMALNE" => "badge-normal",
-        "NIEPILNE" => "badge-low",
-        _ => "badge-normal",
-    };
-    format!(r#"<span class="badge {}">{}</span>"#, class, urgency)
+fn timetable_urgency(changes: &[TimetableChange]) -> Urgency {
+    due_urgency(changes.iter().map(|change| change.date.as_str()))
 }
 
-fn format_markdown(text: &str) -> String {
-    BOLD_RE.replace_all(text, "<strong>$1</strong>").to_string()
+fn reminder_urgency(reminders: &Reminders) -> Urgency {
+    due_urgency(std::iter::once(reminders.due.as_str()))
 }
 
-fn format_date(date_str: &str) -> String {
-    if let Some(date_part) = date_str.split('T').next() {
-        date_part.to_string()
+/// Unexcused absences need an explanation; the rest is for information.
+fn attendance_urgency(attendance: &[AttendanceItem]) -> Urgency {
+    if attendance.iter().any(|item| item.is_unexcused_absence()) {
+        Urgency::Normalne
     } else {
-        date_str.to_string()
+        Urgency::Niepilne
     }
 }
 
-fn get_subject_prefix(urgency: &str) -> &'static str {
-    match urgency {
-        "PILNE" => "[PILNE]",
-        "NIEPILNE" => "[NIEPILNE]",
-        _ => "[NORMALNE]",
-    }
+pub fn has_content(digest: &Digest) -> bool {
+    has_new_items(digest) || !digest.reminders.is_empty()
 }
 
-fn generate_html_email(
-    announcements_data: Option<&Analysis>,
-    messages_data: Option<&Analysis>,
-    grades_data: Option<&Analysis>,
-    homework_data: Option<&Analysis>,
-    announcements: &[AnnouncementItem],
-    messages: &[MessageItem],
-    grades: &[GradeItem],
-    homework: &[HomeworkItem],
-) -> String {
-    let mut html = String::from(
-        r#"<!DOCTYPE html>
-<html>
-<head>
-  <meta charset="utf-8">
-  <meta name="viewport" content="width=device-width, initial-scale=1.0">
-  <style>
-    * { box-sizing: border-box; }
-    body {
-      font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
-      line-height: 1.6;
-      color: #1a1a2e;
-      max-width: 700px;
-      margin: 0 auto;
-      padding: 24px 16px;
-      background: #f8fafc;
-    }
-    .header {
-      background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
-      color: white;
-      padding: 24px;
-      border-radius: 16px;
-      margin-bottom: 24px;
-      text-align: center;
-    }
-    .header h1 {
-      margin: 0;
-      font-size: 24px;
-      font-weight: 600;
-    }
-    .header .date {
-      opacity: 0.9;
-      font-size: 14px;
-      margin-top: 8px;
-    }
-    .section {
-      background: white;
-      border-radius: 12px;
-      padding: 20px;
-      margin-bottom: 20px;
-      box-shadow: 0 1px 3px rgba(0,0,0,0.08);
-    }
-    .section-header {
-      display: flex;
-      align-items: center;
-      gap: 12px;
-      margin-bottom: 16px;
-      padding-bottom: 12px;
-      border-bottom: 2px solid #f1f5f9;
-    }
-    .section-icon {
-      font-size: 24px;
-    }
-    .section-title {
-      font-size: 18px;
-      font-weight: 600;
-      color: #1e293b;
-      margin: 0;
-    }
-    .badge {
-      display: inline-block;
-      padding: 4px 10px;
-      border-radius: 20px;
-      font-size: 11px;
-      font-weight: 600;
-      text-transform: uppercase;
-      letter-spacing: 0.5px;
-    }
-    .badge-urgent { background: #fee2e2; color: #dc2626; }
-    .badge-normal { background: #fef3c7; color: #d97706; }
-    .badge-low { background: #d1fae5; color: #059669; }
-    .summary-box {
-      background: linear-gradient(135deg, #f0f9ff 0%, #e0f2fe 100%);
-      padding: 16px;
-      border-radius: 10px;
-      margin-bottom: 16px;
-      border-left: 4px solid #0ea5e9;
-    }
-    .summary-title {
-      font-weight: 600;
-      color: #0369a1;
-      margin-bottom: 8px;
-      font-size: 13px;
-      text-transform: uppercase;
-      letter-spacing: 0.5px;
-    }
-    .summary-text {
-      color: #334155;
-      font-size: 14px;
-    }
-    .key-points {
-      background: #fafafa;
-      padding: 16px;
-      border-radius: 10px;
-    }
-    .key-points-title {
-      font-weight: 600;
-      color: #475569;
-      margin-bottom: 12px;
-      font-size: 13px;
-      text-transform: uppercase;
-      letter-spacing: 0.5px;
-    }
-    .key-points ul {
-      margin: 0;
-      padding-left: 0;
-      list-style: none;
-    }
-    .key-points li {
-      padding: 8px 0;
-      border-bottom: 1px solid #e2e8f0;
-      font-size: 14px;
-      color: #334155;
-    }
-    .key-points li:last-child { border-bottom: none; }
-    .details-header {
-      font-size: 13px;
-      font-weight: 600;
-      color: #64748b;
-      text-transform: uppercase;
-      letter-spacing: 0.5px;
-      margin: 20px 0 12px 0;
-    }
-    .item {
-      background: #f8fafc;
-      border-radius: 8px;
-      padding: 14px;
-      margin-bottom: 10px;
-      border: 1px solid #e2e8f0;
-    }
-    .item-header {
-      font-weight: 600;
-      color: #1e293b;
-      font-size: 14px;
-      margin-bottom: 6px;
-    }
-    .item-header a {
-      color: #6366f1;
-      text-decoration: none;
-      font-weight: 500;
-      font-size: 12px;
-    }
-    .item-header a:hover { text-decoration: underline; }
-    .item-meta {
-      font-size: 12px;
-      color: #64748b;
-      margin-bottom: 8px;
-    }
-    details { margin-top: 8px; }
-    summary {
-      cursor: pointer;
-      color: #6366f1;
-      font-weight: 500;
-      font-size: 13px;
-      padding: 4px 0;
-    }
-    summary:hover { color: #4f46e5; }
-    .item-content {
-      margin-top: 10px;
-      padding: 12px;
-      background: white;
-      border-radius: 6px;
-      font-size: 13px;
-      color: #475569;
-      white-space: pre-wrap;
-      border: 1px solid #e2e8f0;
-    }
-    .grade-item {
-      background: linear-gradient(135deg, #ecfdf5 0%, #d1fae5 100%);
-      border: 1px solid #a7f3d0;
-    }
-    .footer {
-      text-align: center;
-      padding: 20px;
-      color: #94a3b8;
-      font-size: 12px;
-    }
-    .footer a { color: #6366f1; text-decoration: none; }
-  </style>
-</head>
-<body>
-  <div class="header">
-    <h1>📚 Powiadomienia z Librusa</h1>
-    <div class="date">Emilka • Klasa 1A SP ETE</div>
-  </div>
-"#,
-    );
+/// True when the digest reports anything besides reminders.
+fn has_new_items(digest: &Digest) -> bool {
+    let has_key_points =
+        |data: &Option<Analysis>| data.as_ref().is_some_and(|d| !d.key_points.is_empty());
+    has_key_points(&digest.announcements_analysis)
+        || has_key_points(&digest.messages_analysis)
+        || has_key_points(&digest.grades_analysis)
+        || !digest.grade_changes.is_empty()
+        || has_key_points(&digest.homework_analysis)
+        || has_key_points(&digest.events_analysis)
+        || !digest.attendance.is_empty()
+        || !digest.timetable_changes.is_empty()
+}
 
-    if let Some(data) = announcements_data {
-        html.push_str(&format!(
-            r#"
-  <div class="section">
-    <div class="section-header">
-      <span class="section-icon">📢</span>
-      <h2 class="section-title">Ogłoszenia</h2>
-      {}
-    </div>
-    <div class="summary-box">
-      <div class="summary-title">Podsumowanie</div>
-      <div class="summary-text">{}</div>
-    </div>
-    <div class="key-points">
-      <div class="key-points-title">Do zrobienia</div>
-      <ul>{}</ul>
-    </div>
-    <div class="details-header">Szczegóły ogłoszeń</div>
-"#,
-            generate_urgency_badge(&data.urgency),
-            format_markdown(&data.summary),
-            data.key_points
-                .iter()
-                .map(|point| format!("<li>{}</li>", format_markdown(point)))
-                .collect::<Vec<_>>()
-                .join("")
-        ));
-
-        for (idx, item) in announcements.iter().enumerate() {
-            html.push_str(&format!(
-                r#"
-    <div class="item">
-      <div class="item-header">{}. {}</div>
-      <div class="item-meta">👤 {} • 📅 {}</div>
-      <details>
-        <summary>Pokaż treść</summary>
-        <div class="item-content">{}</div>
-      </details>
-    </div>
-"#,
-                idx + 1,
-                item.title,
-                item.author,
-                format_date(&item.date),
-                item.content
-            ));
+/// Urgency of one section of the digest; `None` when the section is empty.
+pub fn section_urgency(digest: &Digest, section: SectionKind) -> Option<Urgency> {
+    let analysis = |data: &Option<Analysis>| data.as_ref().map(|data| data.urgency);
+    if section.is_empty(digest) {
+        return None;
+    }
+    match section {
+        SectionKind::Announcements => analysis(&digest.announcements_analysis),
+        SectionKind::Messages => analysis(&digest.messages_analysis),
+        SectionKind::Grades => {
+            let changes = (!digest.grade_changes.is_empty()).then_some(Urgency::Normalne);
+            analysis(&digest.grades_analysis).max(changes)
         }
-        html.push_str("  </div>\n");
+        SectionKind::Homework => analysis(&digest.homework_analysis),
+        SectionKind::Events => analysis(&digest.events_analysis),
+        SectionKind::Attendance => Some(attendance_urgency(&digest.attendance)),
+        SectionKind::Timetable => Some(timetable_urgency(&digest.timetable_changes)),
+        SectionKind::Reminders => Some(reminder_urgency(&digest.reminders)),
     }
+}
 
-    if let Some(data) = messages_data {
-        html.push_str(&format!(
-            r#"
-  <div class="section">
-    <div class="section-header">
-      <span class="section-icon">✉️</span>
-      <h2 class="section-title">Wiadomości</h2>
-      {}
-    </div>
-    <div class="summary-box">
-      <div class="summary-title">Podsumowanie</div>
-      <div class="summary-text">{}</div>
-    </div>
-    <div class="key-points">
-      <div class="key-points-title">Do zrobienia</div>
-      <ul>{}</ul>
-    </div>
-    <div class="details-header">Szczegóły wiadomości ({} szt.)</div>
-"#,
-            generate_urgency_badge(&data.urgency),
-            format_markdown(&data.summary),
-            data.key_points
-                .iter()
-                .map(|point| format!("<li>{}</li>", format_markdown(point)))
-                .collect::<Vec<_>>()
-                .join(""),
-            messages.len()
-        ));
-
-        for (idx, item) in messages.iter().enumerate() {
-            let message_url = format!("https://synergia.librus.pl/wiadomosci/1/5/{}/f0", item.id);
-            html.push_str(&format!(
-                r#"
-    <div class="item">
-      <div class="item-header">{}. {} <a href="{}">[Otwórz]</a></div>
-      <div class="item-meta">👤 {} • 📅 {}</div>
-      <details>
-        <summary>Pokaż treść</summary>
-        <div class="item-content">{}</div>
-      </details>
-    </div>
-"#,
-                idx + 1,
-                item.title,
-                message_url,
-                item.user,
-                format_date(&item.date),
-                item.body
-            ));
-        }
-        html.push_str("  </div>\n");
-    }
+/// The highest urgency of anything in the digests; NIEPILNE when there is nothing.
+pub fn max_urgency(digests: &[&Digest]) -> Urgency {
+    digests
+        .iter()
+        .flat_map(|digest| {
+            SectionKind::ALL
+                .into_iter()
+                .filter_map(|section| section_urgency(digest, section))
+        })
+        .max()
+        .unwrap_or(Urgency::Niepilne)
+}
+
+/// Context passed to the email templates.
+#[derive(Debug, Serialize)]
+pub struct EmailContext<'a> {
+    pub generated_at: String,
+    pub urgency: Urgency,
+    pub students: Vec<&'a str>,
+    pub digests: Vec<DigestContext<'a>>,
+}
 
-    if let Some(data) = grades_data {
-        html.push_str(&format!(
-            r#"
-  <div class="section">
-    <div class="section-header">
-      <span class="section-icon">⭐</span>
-      <h2 class="section-title">Nowe oceny</h2>
-      {}
-    </div>
-    <div class="summary-box">
-      <div class="summary-title">Podsumowanie</div>
-      <div class="summary-text">{}</div>
-    </div>
-    <div class="key-points">
-      <div class="key-points-title">Szczegóły</div>
-      <ul>{}</ul>
-    </div>
-    <div class="details-header">Oceny</div>
-"#,
-            generate_urgency_badge(&data.urgency),
-            format_markdown(&data.summary),
-            data.key_points
+/// One student's part of the email; sections without new items are `None`.
+#[derive(Debug, Serialize)]
+pub struct DigestContext<'a> {
+    pub student: &'a str,
+    pub reminders: Option<ReminderSection<'a>>,
+    pub announcements: Option<Section<'a, AnnouncementItem>>,
+    pub messages: Option<Section<'a, MessageItem>>,
+    pub grades: Option<Section<'a, GradeItem>>,
+    pub grade_changes: Vec<GradeChangeView<'a>>,
+    pub homework: Option<Section<'a, HomeworkItem>>,
+    pub events: Option<Section<'a, EventItem>>,
+    pub attendance: Option<AttendanceSection<'a>>,
+    pub timetable: Option<TimetableSection<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Section<'a, T> {
+    pub analysis: &'a Analysis,
+    pub items: &'a [T],
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReminderSection<'a> {
+    pub urgency: Urgency,
+    #[serde(flatten)]
+    pub reminders: &'a Reminders,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GradeChangeView<'a> {
+    pub subject: &'a str,
+    pub value: &'a str,
+    pub details: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceSection<'a> {
+    pub urgency: Urgency,
+    pub unexcused: usize,
+    pub late: usize,
+    pub items: Vec<AttendanceView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceView<'a> {
+    #[serde(flatten)]
+    pub item: &'a AttendanceItem,
+    pub unexcused: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimetableSection<'a> {
+    pub urgency: Urgency,
+    pub changes: Vec<TimetableChangeView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimetableChangeView<'a> {
+    #[serde(flatten)]
+    pub change: &'a TimetableChange,
+    pub canceled: bool,
+}
+
+fn section<'a, T>(analysis: &'a Option<Analysis>, items: &'a [T]) -> Option<Section<'a, T>> {
+    analysis
+        .as_ref()
+        .map(|analysis| Section { analysis, items })
+}
+
+impl<'a> DigestContext<'a> {
+    fn new(digest: &'a Digest) -> Self {
+        let attendance = (!digest.attendance.is_empty()).then(|| {
+            let unexcused = digest
+                .attendance
+                .iter()
+                .filter(|item| item.is_unexcused_absence())
+                .count();
+            AttendanceSection {
+                urgency: attendance_urgency(&digest.attendance),
+                unexcused,
+                late: digest
+                    .attendance
+                    .iter()
+                    .filter(|item| item.is_late())
+                    .count(),
+                items: digest
+                    .attendance
+                    .iter()
+                    .map(|item| AttendanceView {
+                        item,
+                        unexcused: item.is_unexcused_absence(),
+                    })
+                    .collect(),
+            }
+        });
+        let timetable = (!digest.timetable_changes.is_empty()).then(|| TimetableSection {
+            urgency: timetable_urgency(&digest.timetable_changes),
+            changes: digest
+                .timetable_changes
+                .iter()
+                .map(|change| TimetableChangeView {
+                    change,
+                    canceled: change.kind == crate::timetable::CANCELED,
+                })
+                .collect(),
+        });
+
+        Self {
+            student: &digest.student,
+            reminders: (!digest.reminders.is_empty()).then(|| ReminderSection {
+                urgency: reminder_urgency(&digest.reminders),
+                reminders: &digest.reminders,
+            }),
+            announcements: section(&digest.announcements_analysis, &digest.announcements),
+            messages: section(&digest.messages_analysis, &digest.messages),
+            grades: section(&digest.grades_analysis, &digest.grades),
+            grade_changes: digest
+                .grade_changes
                 .iter()
-                .map(|point| format!("<li>{}</li>", format_markdown(point)))
-                .collect::<Vec<_>>()
-                .join("")
-        ));
-
-        for (idx, item) in grades.iter().enumerate() {
-            html.push_str(&format!(
-                r#"
-    <div class="item grade-item">
-      <div class="item-header">{}. {} — {}</div>
-      <details>
-        <summary>Pokaż szczegóły</summary>
-        <div class="item-content">{}</div>
-      </details>
-    </div>
-"#,
-                idx + 1,
-                item.subject,
-                item.value,
-                item.info
-            ));
+                .map(|change| GradeChangeView {
+                    subject: &change.grade.subject,
+                    value: &change.grade.value,
+                    details: change.details(),
+                })
+                .collect(),
+            homework: section(&digest.homework_analysis, &digest.homework),
+            events: section(&digest.events_analysis, &digest.events),
+            attendance,
+            timetable,
         }
-        html.push_str("  </div>\n");
     }
+}
 
-    if let Some(data) = homework_data {
-        html.push_str(&format!(
-            r#"
-  <div class="section">
-    <div class="section-header">
-      <span class="section-icon">📝</span>
-      <h2 class="section-title">Zadania domowe</h2>
-      {}
-    </div>
-    <div class="summary-box">
-      <div class="summary-title">Podsumowanie</div>
-      <div class="summary-text">{}</div>
-    </div>
-    <div class="key-points">
-      <div class="key-points-title">Do zrobienia</div>
-      <ul>{}</ul>
-    </div>
-    <div class="details-header">Szczegóły zadań</div>
-"#,
-            generate_urgency_badge(&data.urgency),
-            format_markdown(&data.summary),
-            data.key_points
+impl<'a> EmailContext<'a> {
+    pub fn new(digests: &[&'a Digest]) -> Self {
+        Self {
+            generated_at: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            urgency: max_urgency(digests),
+            students: digests
+                .iter()
+                .map(|digest| digest.student.as_str())
+                .collect(),
+            digests: digests
                 .iter()
-                .map(|point| format!("<li>{}</li>", format_markdown(point)))
-                .collect::<Vec<_>>()
-                .join("")
-        ));
-
-        for (idx, item) in homework.iter().enumerate() {
-            html.push_str(&format!(
-                r#"
-    <div class="item">
-      <div class="item-header">{}. {} — {}</div>
-      <div class="item-meta">👤 {} • 📅 {} → {}</div>
-      <details>
-        <summary>Pokaż treść</summary>
-        <div class="item-content">{}</div>
-      </details>
-    </div>
-"#,
-                idx + 1,
-                item.subject,
-                item.title,
-                item.user,
-                format_date(&item.from),
-                format_date(&item.to),
-                item.content
-            ));
+                .map(|digest| DigestContext::new(digest))
+                .collect(),
         }
-        html.push_str("  </div>\n");
     }
+}
 
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M");
-    html.push_str(&format!(
-        r#"
-  <div class="footer">
-    Wygenerowano automatycznie {} • <a href="https://synergia.librus.pl">Otwórz Librus</a>
-  </div>
-</body>
-</html>
-"#,
-        timestamp
-    ));
-
-    html
+fn generate_html_email(digests: &[&Digest], template_dir: Option<&Path>) -> Result<String> {
+    template::render(template_dir, "email.html", EmailContext::new(digests))
 }
 
-pub async fn send_notification(
-    announcements_data: Option<&Analysis>,
-    messages_data: Option<&Analysis>,
-    grades_data: Option<&Analysis>,
-    homework_data: Option<&Analysis>,
-    announcements: &[AnnouncementItem],
-    messages: &[MessageItem],
-    grades: &[GradeItem],
-    homework: &[HomeworkItem],
-) -> Result<()> {
-    let has_announcements = announcements_data.map_or(false, |d| !d.key_points.is_empty());
-    let has_messages = messages_data.map_or(false, |d| !d.key_points.is_empty());
-    let has_grades = grades_data.map_or(false, |d| !d.key_points.is_empty());
-    let has_homework = homework_data.map_or(false, |d| !d.key_points.is_empty());
+/// Plain-text alternative of [`generate_html_email`] built from the same digests.
+pub fn generate_text_email(digests: &[&Digest], template_dir: Option<&Path>) -> Result<String> {
+    template::render(template_dir, "email.txt", EmailContext::new(digests))
+}
 
-    if !has_announcements && !has_messages && !has_grades && !has_homework {
-        tracing::info!("No new items to notify about");
-        return Ok(());
+/// SMTP channel; prints the email instead of sending it unless `notify.email.send` is set.
+pub struct EmailNotifier {
+    config: EmailConfig,
+}
+
+impl EmailNotifier {
+    pub fn from_config(config: &EmailConfig) -> Self {
+        if config.send {
+            tracing::info!(
+                recipient_count = config.to.len(),
+                recipients = config.to.join(","),
+                "Email notifications enabled"
+            );
+        } else {
+            tracing::info!("Email sending disabled, emails are only printed");
+        }
+        Self {
+            config: config.clone(),
+        }
     }
+}
 
-    let mut urgencies = Vec::new();
-    if let Some(data) = announcements_data {
-        urgencies.push(data.urgency.as_str());
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
     }
-    if let Some(data) = messages_data {
-        urgencies.push(data.urgency.as_str());
+
+    fn is_push(&self) -> bool {
+        false
     }
-    if let Some(data) = grades_data {
-        urgencies.push(data.urgency.as_str());
+
+    fn delivers(&self) -> bool {
+        self.config.send
     }
-    if let Some(data) = homework_data {
-        urgencies.push(data.urgency.as_str());
+
+    fn sends_each_digest(&self) -> bool {
+        self.config.per_child
     }
 
-    let max_urgency = if urgencies.iter().any(|u| *u == "PILNE") {
-        "PILNE"
-    } else if urgencies.iter().any(|u| *u == "NORMALNE") {
-        "NORMALNE"
-    } else {
-        "NIEPILNE"
-    };
+    /// Sends one combined email for all students, or one email per student when
+    /// `per_child` is set; a failed email does not stop the others.
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        if self.config.per_child {
+            let mut errors = Vec::new();
+            for digest in digests {
+                if let Err(err) = send_email(&self.config, &[digest], Some(&digest.student)).await {
+                    errors.push(err);
+                }
+            }
+            errors.into_iter().next().map_or(Ok(()), Err)
+        } else {
+            send_email(&self.config, digests, None).await
+        }
+    }
+}
 
-    let subject = format!(
-        "{} Nowe powiadomienia z Librusa",
-        get_subject_prefix(max_urgency)
-    );
+async fn send_email(
+    config: &EmailConfig,
+    digests: &[&Digest],
+    student: Option<&str>,
+) -> Result<()> {
+    let max_urgency = max_urgency(digests);
 
-    let html = generate_html_email(
-        announcements_data,
-        messages_data,
-        grades_data,
-        homework_data,
-        announcements,
-        messages,
-        grades,
-        homework,
-    );
+    // A run with nothing new but upcoming deadlines sends only a reminder
+    let title = match digests.first() {
+        Some(digest) if !digests.iter().any(|digest| has_new_items(digest)) => {
+            format!("{}: przypomnienie z Librusa", digest.reminders.label)
+        }
+        _ => "Nowe powiadomienia z Librusa".to_string(),
+    };
+    let mut subject = format!("[{}] {}", max_urgency, title);
+    if let Some(student) = student {
+        subject = format!("{} — {}", subject, student);
+    }
 
-    if !should_send_email() {
+    let template_dir = config.template_dir.as_deref();
+    let html = generate_html_email(digests, template_dir)?;
+    let text = generate_text_email(digests, template_dir)?;
+
+    if !config.send {
         tracing::info!("EMAIL SENDING DISABLED - Notification content:");
         println!("\n{}", "=".repeat(80));
         println!("Subject: {}", subject);
         println!("{}", "=".repeat(80));
-        let preview = TAG_RE.replace_all(&html, " ");
-        let preview = preview.split_whitespace().collect::<Vec<_>>().join(" ");
-        let preview = if preview.len() > 500 {
-            format!("{}...", &preview[..500])
-        } else {
-            preview
-        };
-        println!("\nHTML Preview (simplified):");
-        println!("{}", preview);
+        println!("\nPlain-text part:");
+        println!("{}", text);
         println!("\n{}\n", "=".repeat(80));
         return Ok(());
     }
 
-    let email_to =
-        env::var("EMAIL_TO").map_err(|_| anyhow!("EMAIL_TO must be set when SEND_EMAIL=true"))?;
-    let recipients: Vec<String> = email_to
-        .split(',')
-        .map(|email| email.trim().to_string())
-        .filter(|email| !email.is_empty())
-        .collect();
-
-    if recipients.is_empty() {
-        return Err(anyhow!("EMAIL_TO must contain at least one recipient"));
-    }
-
-    let from = env::var("EMAIL_FROM").or_else(|_| env::var("EMAIL_USER"))?;
+    let recipients = &config.to;
+    let from = if config.from.is_empty() {
+        &config.user
+    } else {
+        &config.from
+    };
 
     let mut builder = Message::builder().from(from.parse()?);
-    for recipient in &recipients {
+    for recipient in recipients {
         builder = builder.to(recipient.parse()?);
     }
 
-    let email = builder
-        .subject(subject)
-        .singlepart(SinglePart::html(html))?;
-
-    let host = env::var("EMAIL_HOST").map_err(|_| anyhow!("EMAIL_HOST must be set"))?;
-    let port: u16 = env::var("EMAIL_PORT")
-        .ok()
-        .and_then(|val| val.parse().ok())
-        .unwrap_or(587);
+    let body = MultiPart::alternative_plain_html(text, html);
+    let events: Vec<CalendarEvent> = digests
+        .iter()
+        .flat_map(|digest| ics::digest_events(digest))
+        .collect();
+    let email = if config.attach_ics && !events.is_empty() {
+        let calendar = ics::to_ics("Librus", &events);
+        let attachment = Attachment::new(ICS_FILENAME.to_string()).body(
+            calendar,
+            ContentType::parse("text/calendar; charset=utf-8")?,
+        );
+        builder
+            .subject(subject)
+            .multipart(MultiPart::mixed().multipart(body).singlepart(attachment))?
+    } else {
+        builder.subject(subject).multipart(body)?
+    };
 
-    let user = env::var("EMAIL_USER").map_err(|_| anyhow!("EMAIL_USER must be set"))?;
-    let password = env::var("EMAIL_PASSWORD").map_err(|_| anyhow!("EMAIL_PASSWORD must be set"))?;
-    let creds = Credentials::new(user, password);
+    let host = &config.host;
+    let port = config.port;
+    let creds = Credentials::new(config.user.clone(), config.password.clone());
 
     let mailer = if port == 465 {
-        AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+        AsyncSmtpTransport::<Tokio1Executor>::relay(host)
             .map_err(|err| anyhow!("Failed to create SMTP transport: {}", err))?
             .port(port)
             .credentials(creds)
             .build()
     } else {
-        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
             .map_err(|err| anyhow!("Failed to create SMTP transport: {}", err))?
             .port(port)
             .credentials(creds)
@@ -589,7 +410,7 @@ pub async fn send_notification(
     };
 
     tracing::info!(
-        urgency = max_urgency,
+        urgency = %max_urgency,
         recipients = recipients.join(","),
         recipient_count = recipients.len(),
         "Sending email notification"
@@ -601,7 +422,7 @@ pub async fn send_notification(
         .map_err(|err| anyhow!(err.to_string()))?;
 
     tracing::info!(
-        urgency = max_urgency,
+        urgency = %max_urgency,
         recipients = recipients.join(","),
         recipient_count = recipients.len(),
         "Email notification sent successfully"
@@ -609,3 +430,98 @@ pub async fn send_notification(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MessageItem;
+
+    #[test]
+    fn test_hostile_message_is_escaped_in_email() {
+        let digest = Digest {
+            student: "Emilka <3".to_string(),
+            messages_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Zebranie o 17:00 & składka".to_string(),
+                key_points: vec!["**<i>jutro</i>**".to_string()],
+                action_items: Vec::new(),
+            }),
+            messages: vec![MessageItem {
+                id: "1\"><script>".to_string(),
+                title: "Wycieczka <script>alert(1)</script>".to_string(),
+                body: "Proszę o zgodę </div></body>".to_string(),
+                date: "2025-03-10T08:00:00".to_string(),
+                user: "Anna Nowak".to_string(),
+            }],
+            ..Digest::default()
+        };
+        let html = generate_html_email(&[&digest], None).unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("Wycieczka &lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+        assert!(!html.contains("</div></body>"));
+        assert!(html.contains("Proszę o zgodę &lt;&#x2f;div&gt;&lt;&#x2f;body&gt;"));
+        assert!(html.contains("Emilka &lt;3"));
+        assert!(html.contains("Zebranie o 17:00 &amp; składka"));
+        assert!(html.contains("<strong>&lt;i&gt;jutro&lt;/i&gt;</strong>"));
+    }
+
+    #[test]
+    fn test_text_email_has_sections_bullets_and_links() {
+        let digest = Digest {
+            student: "Emilka".to_string(),
+            messages_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Zgoda na wycieczkę do **piątku**.".to_string(),
+                key_points: vec!["✍️ **piątek** - podpisać zgodę".to_string()],
+                action_items: Vec::new(),
+            }),
+            messages: vec![MessageItem {
+                id: "42".to_string(),
+                title: "Wycieczka".to_string(),
+                body: "Proszę o zgodę.\nPozdrawiam".to_string(),
+                date: "2025-03-10T08:00:00".to_string(),
+                user: "Anna Nowak".to_string(),
+            }],
+            ..Digest::default()
+        };
+        let text = generate_text_email(&[&digest], None).unwrap();
+        assert!(text.contains("✉️ WIADOMOŚCI [PILNE]"));
+        assert!(text.contains("Zgoda na wycieczkę do piątku."));
+        assert!(text.contains("Do zrobienia:\n  * ✍️ piątek - podpisać zgodę"));
+        assert!(text.contains("https://synergia.librus.pl/wiadomosci/1/5/42/f0"));
+        assert!(text.contains("   Proszę o zgodę.\n   Pozdrawiam"));
+        assert!(!text.contains('<'));
+    }
+
+    #[test]
+    fn test_reminder_only_digest_passes_send_gate() {
+        let digest = Digest {
+            student: "Emilka".to_string(),
+            reminders: Reminders {
+                due: "2025-03-14".to_string(),
+                label: "Jutro".to_string(),
+                homework: vec![HomeworkItem {
+                    id: "7".to_string(),
+                    subject: "Polski".to_string(),
+                    title: "Czytanka str. 12".to_string(),
+                    kind: "Zadanie domowe".to_string(),
+                    from: "2025-03-10".to_string(),
+                    to: "2025-03-14".to_string(),
+                    content: "Przeczytać czytankę.".to_string(),
+                    user: "Anna Nowak".to_string(),
+                }],
+                events: Vec::new(),
+            },
+            ..Digest::default()
+        };
+        // Neither the per-account check nor the channel filter drops it
+        assert!(!digest.is_empty());
+        assert!(has_content(&digest));
+        assert!(!has_new_items(&digest));
+        assert_eq!(max_urgency(&[&digest]), Urgency::Pilne);
+
+        let text = generate_text_email(&[&digest], None).unwrap();
+        assert!(text.contains("⏰ JUTRO [PILNE]"));
+        assert!(text.contains("Terminy na 2025-03-14:\n  * 📝 Polski — Czytanka str. 12"));
+    }
+}
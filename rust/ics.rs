@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDate, Utc};
+
+use crate::models::{ActionItem, Digest, EventItem, HomeworkItem};
+
+const PRODID: &str = "-//czujka-librus//Librus notifications//PL";
+// RFC 5545 limit, in octets, before a content line must be folded
+const MAX_LINE_OCTETS: usize = 75;
+
+/// An all-day calendar entry.
+///
+/// The uid only depends on the account and the Librus id (or the task key of an action
+/// item), so calendars update the entry instead of duplicating it when the same
+/// item is exported again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub date: NaiveDate,
+    pub summary: String,
+    pub description: String,
+}
+
+impl CalendarEvent {
+    /// Homework on its due date.
+    pub fn homework(account: &str, student: &str, item: &HomeworkItem) -> Option<Self> {
+        Some(Self {
+            uid: uid("homework", account, &item.id),
+            date: parse_date(&item.to)?,
+            summary: format!("[{}] {} — {}", student, item.subject, item.title),
+            description: format!("{}\n\nNauczyciel: {}", item.content.trim(), item.user),
+        })
+    }
+
+    pub fn event(account: &str, student: &str, item: &EventItem) -> Option<Self> {
+        Some(Self {
+            uid: uid("event", account, &item.id),
+            date: parse_date(&item.day)?,
+            summary: format!("[{}] {}", student, item.title),
+            description: item.description.trim().to_string(),
+        })
+    }
+
+    /// An action item the model found in the `source` section, on its due date.
+    pub fn action(account: &str, student: &str, source: &str, item: &ActionItem) -> Option<Self> {
+        let due = item.due.as_deref()?;
+        Some(Self {
+            uid: uid("task", account, &item.key(source)),
+            date: parse_date(due)?,
+            summary: format!("[{}] {}", student, item.description),
+            description: match item.amount_pln {
+                Some(amount) => format!("{} • {:.2} zł", item.kind.label(), amount),
+                None => item.kind.label().to_string(),
+            },
+        })
+    }
+}
+
+fn uid(kind: &str, account: &str, id: &str) -> String {
+    if account.is_empty() {
+        format!("{}-{}@czujka-librus", kind, id)
+    } else {
+        format!("{}-{}-{}@czujka-librus", kind, account, id)
+    }
+}
+
+/// Reads the `YYYY-MM-DD` prefix of Librus dates and model output.
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// Calendar entries for everything dated in a digest: new homework and events, the
+/// reminded ones and the dated action items found by the model.
+pub fn digest_events(digest: &Digest) -> Vec<CalendarEvent> {
+    let (account, student) = (digest.account.as_str(), digest.student.as_str());
+    let homework = digest.homework.iter().chain(&digest.reminders.homework);
+    let events = digest.events.iter().chain(&digest.reminders.events);
+    let actions = digest
+        .analyses_by_source()
+        .into_iter()
+        .flat_map(|(source, analysis)| {
+            analysis.iter().flat_map(move |analysis| {
+                analysis.action_items.iter().map(move |item| (source, item))
+            })
+        });
+
+    let mut seen = HashSet::new();
+    homework
+        .filter_map(|item| CalendarEvent::homework(account, student, item))
+        .chain(events.filter_map(|item| CalendarEvent::event(account, student, item)))
+        .chain(
+            actions
+                .filter_map(|(source, item)| CalendarEvent::action(account, student, source, item)),
+        )
+        .filter(|event| seen.insert(event.uid.clone()))
+        .collect()
+}
+
+/// Serialises the entries as an iCalendar (RFC 5545) file named `name`.
+pub fn to_ics(name: &str, events: &[CalendarEvent]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+    for event in events {
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", event.uid),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", event.date.format("%Y%m%d")),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                (event.date + Duration::days(1)).format("%Y%m%d")
+            ),
+            format!("SUMMARY:{}", escape_text(&event.summary)),
+            format!("DESCRIPTION:{}", escape_text(&event.description)),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        ics.push_str(&fold_line(&line));
+        ics.push_str("\r\n");
+    }
+    ics
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits a content line into 75-octet pieces without cutting a UTF-8 character.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // The leading space counts towards the continuation line
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ActionKind, Analysis, Reminders, Urgency};
+
+    fn homework(id: &str, to: &str) -> HomeworkItem {
+        HomeworkItem {
+            id: id.to_string(),
+            subject: "Polski".to_string(),
+            title: "Czytanka; str. 12, 13".to_string(),
+            kind: "Zadanie domowe".to_string(),
+            from: "2025-03-10".to_string(),
+            to: to.to_string(),
+            content: "Przeczytać czytankę.".to_string(),
+            user: "Anna Nowak".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_digest_events_cover_homework_events_and_action_items() {
+        let digest = Digest {
+            account: "emilka".to_string(),
+            student: "Emilka".to_string(),
+            homework: vec![homework("1", "2025-03-14"), homework("2", "brak")],
+            messages_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Wycieczka".to_string(),
+                key_points: Vec::new(),
+                action_items: vec![
+                    ActionItem {
+                        kind: ActionKind::Payment,
+                        due: Some("2025-03-20".to_string()),
+                        amount_pln: Some(20.0),
+                        description: "Wpłata na wycieczkę".to_string(),
+                        source_id: Some("42".to_string()),
+                    },
+                    ActionItem {
+                        kind: ActionKind::Supplies,
+                        due: None,
+                        amount_pln: None,
+                        description: "Bibuła na plastykę".to_string(),
+                        source_id: None,
+                    },
+                ],
+            }),
+            reminders: Reminders {
+                homework: vec![homework("1", "2025-03-14")],
+                ..Reminders::default()
+            },
+            ..Digest::default()
+        };
+
+        let events = digest_events(&digest);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].uid, "homework-emilka-1@czujka-librus");
+        assert_eq!(
+            events[0].date,
+            NaiveDate::from_ymd_opt(2025, 3, 14).unwrap()
+        );
+        assert_eq!(events[1].summary, "[Emilka] Wpłata na wycieczkę");
+        let item = &digest.messages_analysis.as_ref().unwrap().action_items[0];
+        assert_eq!(
+            events[1].uid,
+            format!("task-emilka-{}@czujka-librus", item.key("messages"))
+        );
+        assert_eq!(events[1].description, "💰 Wpłata • 20.00 zł");
+    }
+
+    #[test]
+    fn test_ics_is_escaped_and_folded() {
+        let event = CalendarEvent::homework("", "Emilka", &homework("1", "2025-03-14")).unwrap();
+        let ics = to_ics("Librus", &[event]);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("UID:homework-1@czujka-librus\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20250314\r\nDTEND;VALUE=DATE:20250315\r\n"));
+        assert!(ics.contains("SUMMARY:[Emilka] Polski — Czytanka\\; str. 12\\, 13\r\n"));
+        assert!(ics.contains("DESCRIPTION:Przeczytać czytankę.\\n\\nNauczyciel: Anna Nowak"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+
+        let long = fold_line(&format!("SUMMARY:{}", "ż".repeat(60)));
+        assert!(long.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert_eq!(
+            long.replace("\r\n ", ""),
+            format!("SUMMARY:{}", "ż".repeat(60))
+        );
+    }
+}
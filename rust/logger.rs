@@ -1,8 +1,7 @@
 use std::env;
 use tracing_subscriber::EnvFilter;
 
-pub fn init_logging() {
-    let level = env::var("LOG_LEVEL").unwrap_or_else(|_| "INFO".to_string());
+pub fn init_logging(level: &str) {
     let level = level.to_lowercase();
 
     let filter = match env::var("RUST_LOG") {
@@ -1,179 +1,671 @@
+mod accounts;
+mod analyzer;
+mod anthropic;
+mod cli;
+mod config;
 mod db;
+mod delivery;
 mod email;
+mod ics;
 mod logger;
+mod matrix;
 mod models;
+mod notifier;
+mod ntfy;
 mod openai;
+mod profile;
+mod retry;
+mod routing;
+mod schedule;
+mod telegram;
+mod template;
 mod text_utils;
+mod timetable;
+mod webhook;
 
 use std::collections::{HashMap, HashSet};
-use std::env;
+use std::path::Path;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, Local, NaiveDateTime};
+use clap::Parser;
 use librus_rs::Client;
-
-use crate::db::Database;
-use crate::email::send_notification;
-use crate::models::{AnnouncementItem, GradeItem, HomeworkItem, MessageItem};
-use crate::openai::OpenAiClient;
-
-const DEFAULT_DB_PATH: &str = "./librus.db";
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::accounts::Account;
+use crate::analyzer::Analyzer;
+use crate::cli::{Cli, Command};
+use crate::config::{Config, LibrusConfig, ReminderConfig};
+use crate::db::{Database, ItemKind, NotifyState};
+use crate::delivery::Delivery;
+use crate::ics::CalendarEvent;
+use crate::models::{
+    AnnouncementItem, AttendanceItem, Digest, EventItem, GradeChange, GradeItem, HomeworkItem,
+    MessageItem, Reminders, TimetableChange, TimetableLesson, TimetableSnapshot, Urgency,
+};
+use crate::profile::StudentProfile;
+use crate::retry::LlmError;
+use crate::routing::{retain_sections, Router};
+use crate::schedule::Schedule;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
-    logger::init_logging();
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or_default();
+    let config = Config::load(cli.config.as_deref(), command.config_scope())?;
+    logger::init_logging(&config.log_level);
+
+    match command {
+        Command::Run { daemon: false } => {
+            Service::new(config).run(&mut HashMap::new()).await?;
+            tracing::info!("Service completed successfully");
+        }
+        Command::Run { daemon: true } => {
+            let schedule: Schedule = config.daemon.schedule.parse()?;
+            run_daemon(&Service::new(config), &schedule).await?;
+        }
+        Command::DryRun => Service::new(config).dry_run().await?,
+        Command::Backfill => backfill(&config).await?,
+        Command::Show { kind, limit } => show(&config, kind, limit)?,
+        Command::Reset { kind, id } => reset(&config, kind, id.as_deref())?,
+        Command::Tasks { all } => tasks(&config, all)?,
+        Command::Done { ids } => complete_tasks(&config, &ids)?,
+        Command::ExportIcs { output } => export_ics(&config, &output)?,
+    }
+    Ok(())
+}
+
+/// Everything one fetch-analyse-notify run needs, built once per process.
+struct Service {
+    config: Config,
+    router: Router,
+    analyzer: Box<dyn Analyzer>,
+}
+
+impl Service {
+    fn new(config: Config) -> Self {
+        tracing::info!(
+            account_count = config.accounts.len(),
+            "Starting Librus notification service"
+        );
+
+        let router = Router::from_config(&config.notify);
+        tracing::info!(
+            routes = router.describe().join("; "),
+            "Notification channels"
+        );
+
+        let analyzer = analyzer::analyzer_from_config(&config.llm);
+        tracing::info!(provider = analyzer.name(), "Using LLM provider");
+
+        Self {
+            config,
+            router,
+            analyzer,
+        }
+    }
 
-    ensure_required_env()?;
+    /// Runs the pipeline once. `clients` keeps Librus sessions between daemon runs.
+    async fn run(&self, clients: &mut HashMap<String, Client>) -> Result<()> {
+        let delivery = Delivery::from_config(&self.config.notify.digest)?;
+        let now = Local::now().naive_local();
+        let mut digests = Vec::new();
+        let mut databases = Vec::new();
+        let mut errors = Vec::new();
+        for account in &self.config.accounts {
+            let db = Database::new(&self.config.db_path, &account.id)
+                .context("Failed to initialize database")?;
+            match self.process(account, clients, &db).await {
+                Ok(Some(digest)) => {
+                    digests.push(digest);
+                    databases.push(db);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::error!(
+                        account = %account.id,
+                        error = %err,
+                        "Failed to process Librus account"
+                    );
+                    errors.push(err);
+                }
+            }
+        }
 
-    let db_path = env::var("DB_PATH").unwrap_or_else(|_| DEFAULT_DB_PATH.to_string());
-    let db = Database::new(&db_path).context("Failed to initialize database")?;
+        if errors.len() == self.config.accounts.len() {
+            return Err(errors.remove(0));
+        }
 
-    tracing::info!("Starting Librus notification service");
+        if digests.is_empty() {
+            tracing::info!("No new items to process");
+        } else {
+            let (instant, held): (Vec<Digest>, Vec<Digest>) = digests
+                .iter()
+                .map(|digest| delivery.split(digest, now))
+                .unzip();
+            let dispatched = self.router.dispatch(&instant).await;
+            for (((db, digest), held), delivered) in databases
+                .iter()
+                .zip(&digests)
+                .zip(&held)
+                .zip(&dispatched.delivered)
+            {
+                if !held.is_empty() {
+                    db.queue_digest(held, now)?;
+                }
+                // Undelivered sections stay pending, so the next run reports them again
+                db.mark_notified(&retain_sections(digest, |section| {
+                    !section.is_empty(delivered) || !section.is_empty(held)
+                }))?;
+            }
+            if let Some(err) = dispatched.error {
+                return Err(err);
+            }
+        }
+        self.flush_queue(&delivery, now).await
+    }
 
-    if env::var("SEND_EMAIL").map(|v| v == "true").unwrap_or(false) {
-        if let Ok(email_to) = env::var("EMAIL_TO") {
-            let recipients: Vec<_> = email_to
-                .split(',')
-                .map(|email| email.trim())
-                .filter(|email| !email.is_empty())
+    /// Sends the queued sections of every account whose digest time has come since
+    /// its queue was last sent. Sections that were not delivered stay queued and the
+    /// next run tries them again.
+    async fn flush_queue(&self, delivery: &Delivery, now: NaiveDateTime) -> Result<()> {
+        let Some(slot) = delivery.last_slot(now) else {
+            tracing::info!("Quiet hours, holding back queued digests");
+            return Ok(());
+        };
+
+        let mut digests = Vec::new();
+        let mut flushes = Vec::new();
+        for account in &self.config.accounts {
+            let db = Database::new(&self.config.db_path, &account.id)
+                .context("Failed to initialize database")?;
+            let queued = db.queued_digests()?;
+            let Some(oldest) = queued.first() else {
+                continue;
+            };
+            if !delivery::flush_due(db.last_flush()?, oldest.queued_at, slot) {
+                continue;
+            }
+            let ids: Vec<i64> = queued.iter().map(|queued| queued.id).collect();
+            let queued_at = oldest.queued_at;
+            let merged = delivery::merge(queued.into_iter().map(|queued| queued.digest).collect());
+            flushes.push((
+                db,
+                ids,
+                queued_at,
+                digests.len()..digests.len() + merged.len(),
+            ));
+            digests.extend(merged);
+        }
+        if digests.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!(count = digests.len(), "Sending queued digests");
+        let dispatched = self.router.dispatch(&digests).await;
+        for (db, ids, queued_at, range) in flushes {
+            let undelivered: Vec<Digest> = digests[range.clone()]
+                .iter()
+                .zip(&dispatched.delivered[range])
+                .map(|(digest, delivered)| {
+                    retain_sections(digest, |section| section.is_empty(delivered))
+                })
+                .filter(|digest| !digest.is_empty())
                 .collect();
-            tracing::info!(
-                recipient_count = recipients.len(),
-                recipients = recipients.join(","),
-                "Email notifications enabled"
+            if undelivered.is_empty() {
+                db.finish_flush(&ids, now)?;
+            } else {
+                db.requeue(&ids, &undelivered, queued_at)?;
+            }
+        }
+        dispatched.error.map_or(Ok(()), Err)
+    }
+
+    /// Fetches and analyses like `run`, but against an in-memory copy of the database,
+    /// printing the plain-text email instead of notifying anyone.
+    async fn dry_run(&self) -> Result<()> {
+        let mut clients = HashMap::new();
+        let mut digests = Vec::new();
+        for account in &self.config.accounts {
+            let db = Database::scratch_copy(&self.config.db_path, &account.id)?;
+            if let Some(digest) = self.process(account, &mut clients, &db).await? {
+                digests.push(digest);
+            }
+        }
+
+        if digests.is_empty() {
+            tracing::info!("No new items to process");
+            return Ok(());
+        }
+        let digests: Vec<&Digest> = digests.iter().collect();
+        let template_dir = self.config.notify.email.template_dir.as_deref();
+        println!("{}", email::generate_text_email(&digests, template_dir)?);
+        Ok(())
+    }
+
+    async fn process(
+        &self,
+        account: &Account,
+        clients: &mut HashMap<String, Client>,
+        db: &Database,
+    ) -> Result<Option<Digest>> {
+        let mut digest = collect_with_session(account, clients, db, &self.config.librus).await?;
+        digest.reminders = collect_reminders(db, &self.config.reminders)?;
+        if digest.is_empty() {
+            tracing::info!(student = %digest.student, "No new items for student");
+            return Ok(None);
+        }
+        analyze_digest(&mut digest, &account.profile, self.analyzer.as_ref()).await;
+        Ok(Some(digest))
+    }
+}
+
+/// Stores everything currently in Librus as notified without analysing or sending it,
+/// so the first real run only reports what is new after installation.
+async fn backfill(config: &Config) -> Result<()> {
+    let mut clients = HashMap::new();
+    for account in &config.accounts {
+        let db =
+            Database::new(&config.db_path, &account.id).context("Failed to initialize database")?;
+        let digest = collect_with_session(account, &mut clients, &db, &config.librus).await?;
+        db.mark_notified(&digest)?;
+        tracing::info!(
+            account = %account.id,
+            announcements = digest.announcements.len(),
+            messages = digest.messages.len(),
+            grades = digest.grades.len() + digest.grade_changes.len(),
+            homework = digest.homework.len(),
+            events = digest.events.len(),
+            attendance = digest.attendance.len(),
+            "Marked current items as seen"
+        );
+    }
+    Ok(())
+}
+
+fn show(config: &Config, kind: ItemKind, limit: usize) -> Result<()> {
+    for account in &config.accounts {
+        let db =
+            Database::new(&config.db_path, &account.id).context("Failed to initialize database")?;
+        let items = db.stored_items(kind, limit)?;
+        println!(
+            "{} — {} ({})",
+            account.profile.label(),
+            kind.table(),
+            items.len()
+        );
+        for item in items {
+            println!(
+                "  {:<8}  {:<10}  {:<12}  {}",
+                item.notify_state,
+                item.fetched_at.split('T').next().unwrap_or_default(),
+                item.id,
+                item.summary
             );
-        } else {
-            tracing::info!("Email notifications enabled, but EMAIL_TO is not set");
         }
-    } else {
-        tracing::info!("Email notifications disabled");
     }
+    Ok(())
+}
+
+fn reset(config: &Config, kind: ItemKind, id: Option<&str>) -> Result<()> {
+    for account in &config.accounts {
+        let db =
+            Database::new(&config.db_path, &account.id).context("Failed to initialize database")?;
+        let removed = db.forget(kind, id)?;
+        tracing::info!(
+            account = %account.id,
+            kind = kind.table(),
+            removed,
+            "Forgot stored items"
+        );
+    }
+    Ok(())
+}
+
+fn tasks(config: &Config, include_done: bool) -> Result<()> {
+    for account in &config.accounts {
+        let db =
+            Database::new(&config.db_path, &account.id).context("Failed to initialize database")?;
+        let tasks = db.tasks(include_done)?;
+        println!("{} — tasks ({})", account.profile.label(), tasks.len());
+        for task in tasks {
+            let mut line = format!(
+                "  {:>4}  {:<10}  {:<14}  {}",
+                task.id,
+                task.item.due.as_deref().unwrap_or("-"),
+                task.item.kind.label(),
+                task.item.description
+            );
+            if let Some(amount) = task.item.amount_pln {
+                line.push_str(&format!(" ({:.2} zł)", amount));
+            }
+            match &task.item.source_id {
+                Some(id) => line.push_str(&format!(" [{} {}]", task.source, id)),
+                None => line.push_str(&format!(" [{}]", task.source)),
+            }
+            if task.done_at.is_some() {
+                line.push_str(" ✓");
+            }
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+fn complete_tasks(config: &Config, ids: &[i64]) -> Result<()> {
+    for &id in ids {
+        let mut completed = false;
+        for account in &config.accounts {
+            let db = Database::new(&config.db_path, &account.id)
+                .context("Failed to initialize database")?;
+            completed |= db.complete_task(id)?;
+        }
+        if !completed {
+            bail!("No open task with id {}", id);
+        }
+        tracing::info!(id, "Marked task as done");
+    }
+    Ok(())
+}
 
-    tracing::info!("Authenticating with Librus");
-    let mut client = Client::from_env()
+/// Writes every stored homework, calendar event and open task of all accounts to one
+/// iCalendar file, e.g. for a calendar subscription served by a web server.
+fn export_ics(config: &Config, output: &Path) -> Result<()> {
+    let mut events = Vec::new();
+    for account in &config.accounts {
+        let db =
+            Database::new(&config.db_path, &account.id).context("Failed to initialize database")?;
+        let student = account.profile.label();
+        let homework = db.calendar_homework()?;
+        let calendar_events = db.calendar_events()?;
+        let tasks = db.tasks(false)?;
+        events.extend(
+            homework
+                .iter()
+                .filter_map(|item| CalendarEvent::homework(&account.id, &student, item)),
+        );
+        events.extend(
+            calendar_events
+                .iter()
+                .filter_map(|item| CalendarEvent::event(&account.id, &student, item)),
+        );
+        events.extend(tasks.iter().filter_map(|task| {
+            CalendarEvent::action(&account.id, &student, &task.source, &task.item)
+        }));
+    }
+
+    std::fs::write(output, ics::to_ics("Librus", &events))
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    tracing::info!(path = %output.display(), count = events.len(), "Exported calendar");
+    Ok(())
+}
+
+/// Runs the service on `schedule` until SIGTERM or Ctrl+C.
+///
+/// A run in progress is never interrupted; a signal received meanwhile stops the
+/// daemon as soon as the run finishes.
+async fn run_daemon(service: &Service, schedule: &Schedule) -> Result<()> {
+    // Both streams live across runs, so a signal arriving during a run is not lost
+    let mut sigterm =
+        signal(SignalKind::terminate()).context("Failed to install SIGTERM handler")?;
+    let mut sigint = signal(SignalKind::interrupt()).context("Failed to install Ctrl+C handler")?;
+    let mut clients = HashMap::new();
+    loop {
+        let next_run = schedule
+            .next_after(Local::now())
+            .context("Schedule never fires")?;
+        tracing::info!(next_run = %next_run.format("%Y-%m-%d %H:%M"), "Waiting for next run");
+        let wait = (next_run - Local::now()).to_std().unwrap_or_default();
+
+        tokio::select! {
+            biased;
+            _ = sigterm.recv() => {
+                tracing::info!("Received SIGTERM, shutting down");
+                return Ok(());
+            }
+            _ = sigint.recv() => {
+                tracing::info!("Received Ctrl+C, shutting down");
+                return Ok(());
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+
+        match service.run(&mut clients).await {
+            Ok(()) => tracing::info!("Scheduled run completed"),
+            Err(err) => tracing::error!(error = %format!("{:#}", err), "Scheduled run failed"),
+        }
+    }
+}
+
+async fn login(account: &Account) -> Result<Client> {
+    tracing::info!(account = %account.id, "Authenticating with Librus");
+    let client = Client::login(&account.username, &account.password)
         .await
         .context("Librus authentication failed")?;
     tracing::info!("Authentication successful");
+    Ok(client)
+}
 
-    let new_announcements = fetch_new_announcements(&client, &db).await?;
-    let new_messages = fetch_new_messages(&mut client, &db).await?;
-    let new_grades = fetch_new_grades(&client, &db).await?;
-    let new_homeworks = fetch_new_homeworks(&client, &db).await?;
-
-    if new_announcements.is_empty()
-        && new_messages.is_empty()
-        && new_grades.is_empty()
-        && new_homeworks.is_empty()
-    {
-        tracing::info!("No new items to process");
-        return Ok(());
+/// Fetches new items of an account with its cached Librus session, logging in again
+/// when there is none or the cached one no longer works.
+async fn collect_with_session(
+    account: &Account,
+    clients: &mut HashMap<String, Client>,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<Digest> {
+    if let Some(mut client) = clients.remove(&account.id) {
+        match collect_account(account, &mut client, db, config).await {
+            Ok(digest) => {
+                clients.insert(account.id.clone(), client);
+                return Ok(digest);
+            }
+            Err(err) => tracing::warn!(
+                account = %account.id,
+                error = %err,
+                "Run with the cached Librus session failed, logging in again"
+            ),
+        }
     }
 
-    let openai = OpenAiClient::from_env()?;
+    let mut client = login(account).await?;
+    let digest = collect_account(account, &mut client, db, config).await?;
+    clients.insert(account.id.clone(), client);
+    Ok(digest)
+}
 
-    let announcements_analysis = if new_announcements.is_empty() {
+/// Fetches new items into a digest without analyses; they are stored as pending.
+async fn collect_account(
+    account: &Account,
+    client: &mut Client,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<Digest> {
+    let student = account.profile.label();
+    tracing::info!(account = %account.id, student = %student, "Fetching new items");
+
+    let announcements = fetch_new_announcements(client, db, config).await?;
+    let messages = fetch_new_messages(client, db, config).await?;
+    let (grades, grade_changes) = fetch_new_grades(client, db).await?;
+    let homework = fetch_new_homeworks(client, db).await?;
+    let events = fetch_new_events(client, db).await?;
+    let attendance = fetch_new_attendance(client, db, config).await?;
+    let (timetable_changes, timetable_snapshot) =
+        fetch_timetable_changes(client, db, config).await?;
+
+    Ok(Digest {
+        account: account.id.clone(),
+        student,
+        announcements,
+        messages,
+        grades,
+        grade_changes,
+        homework,
+        events,
+        attendance,
+        timetable_changes,
+        timetable_snapshot,
+        ..Digest::default()
+    })
+}
+
+/// Stored homework and events due `days_ahead` days from today that were not reminded
+/// about yet. Reminders go out even when nothing new arrived.
+fn collect_reminders(db: &Database, config: &ReminderConfig) -> Result<Reminders> {
+    if config.days_ahead == 0 {
+        return Ok(Reminders::default());
+    }
+    let due = (Local::now().date_naive() + Duration::days(config.days_ahead.into()))
+        .format("%Y-%m-%d")
+        .to_string();
+    let label = match config.days_ahead {
+        1 => "Jutro".to_string(),
+        2 => "Pojutrze".to_string(),
+        days => format!("Za {} dni", days),
+    };
+    let reminders = Reminders {
+        homework: db.due_homework(&due)?,
+        events: db.due_events(&due)?,
+        due,
+        label,
+    };
+    if !reminders.is_empty() {
+        tracing::info!(
+            due = %reminders.due,
+            homework = reminders.homework.len(),
+            events = reminders.events.len(),
+            "Found upcoming deadlines to remind about"
+        );
+    }
+    Ok(reminders)
+}
+
+/// Adds the LLM analysis of every non-empty section, falling back to a plain listing
+/// when the model fails.
+async fn analyze_digest(digest: &mut Digest, profile: &StudentProfile, analyzer: &dyn Analyzer) {
+    let announcements_analysis = if digest.announcements.is_empty() {
         None
     } else {
-        tracing::info!("Analyzing announcements with OpenAI");
-        match openai.summarize_announcements(&new_announcements).await {
+        tracing::info!("Analyzing announcements with LLM");
+        match analyzer
+            .summarize_announcements(&digest.announcements, profile)
+            .await
+        {
             Ok(result) => Some(result),
             Err(err) => {
-                tracing::error!(error = %err, "OpenAI analysis failed for announcements");
-                Some(fallback_announcements(&new_announcements))
+                tracing::error!(
+                    error = %err,
+                    transient = LlmError::is_transient_error(&err),
+                    "LLM analysis failed for announcements"
+                );
+                Some(fallback_announcements(&digest.announcements))
             }
         }
     };
 
-    let messages_analysis = if new_messages.is_empty() {
+    let messages_analysis = if digest.messages.is_empty() {
         None
     } else {
-        tracing::info!("Analyzing messages with OpenAI");
-        match openai.summarize_messages(&new_messages).await {
+        tracing::info!("Analyzing messages with LLM");
+        match analyzer.summarize_messages(&digest.messages, profile).await {
             Ok(result) => Some(result),
             Err(err) => {
-                tracing::error!(error = %err, "OpenAI analysis failed for messages");
-                Some(fallback_messages(&new_messages))
+                tracing::error!(
+                    error = %err,
+                    transient = LlmError::is_transient_error(&err),
+                    "LLM analysis failed for messages"
+                );
+                Some(fallback_messages(&digest.messages))
             }
         }
     };
 
-    let grades_analysis = if new_grades.is_empty() {
+    let grades_analysis = if digest.grades.is_empty() {
         None
     } else {
-        tracing::info!("Analyzing grades with OpenAI");
-        match openai.summarize_grades(&new_grades).await {
+        tracing::info!("Analyzing grades with LLM");
+        match analyzer.summarize_grades(&digest.grades, profile).await {
             Ok(result) => Some(result),
             Err(err) => {
-                tracing::error!(error = %err, "OpenAI analysis failed for grades");
-                Some(fallback_grades(&new_grades))
+                tracing::error!(
+                    error = %err,
+                    transient = LlmError::is_transient_error(&err),
+                    "LLM analysis failed for grades"
+                );
+                Some(fallback_grades(&digest.grades))
             }
         }
     };
 
-    let homework_analysis = if new_homeworks.is_empty() {
+    let homework_analysis = if digest.homework.is_empty() {
         None
     } else {
-        tracing::info!("Analyzing homework with OpenAI");
-        match openai.summarize_homework(&new_homeworks).await {
+        tracing::info!("Analyzing homework with LLM");
+        match analyzer.summarize_homework(&digest.homework, profile).await {
             Ok(result) => Some(result),
             Err(err) => {
-                tracing::error!(error = %err, "OpenAI analysis failed for homework");
-                Some(fallback_homework(&new_homeworks))
+                tracing::error!(
+                    error = %err,
+                    transient = LlmError::is_transient_error(&err),
+                    "LLM analysis failed for homework"
+                );
+                Some(fallback_homework(&digest.homework))
             }
         }
     };
 
-    send_notification(
-        announcements_analysis.as_ref(),
-        messages_analysis.as_ref(),
-        grades_analysis.as_ref(),
-        homework_analysis.as_ref(),
-        &new_announcements,
-        &new_messages,
-        &new_grades,
-        &new_homeworks,
-    )
-    .await?;
-
-    tracing::info!("Service completed successfully");
-    Ok(())
-}
+    let events_analysis = if digest.events.is_empty() {
+        None
+    } else {
+        tracing::info!("Analyzing events with LLM");
+        match analyzer.summarize_events(&digest.events, profile).await {
+            Ok(result) => Some(result),
+            Err(err) => {
+                tracing::error!(
+                    error = %err,
+                    transient = LlmError::is_transient_error(&err),
+                    "LLM analysis failed for events"
+                );
+                Some(fallback_events(&digest.events))
+            }
+        }
+    };
 
-fn ensure_required_env() -> Result<()> {
-    if env::var("LIBRUS_USERNAME").is_err() || env::var("LIBRUS_PASSWORD").is_err() {
-        anyhow::bail!("LIBRUS_USERNAME and LIBRUS_PASSWORD must be set in .env file");
-    }
-    if env::var("OPENAI_API_KEY").is_err() {
-        anyhow::bail!("OPENAI_API_KEY must be set in .env file");
-    }
-    Ok(())
+    digest.announcements_analysis = announcements_analysis;
+    digest.messages_analysis = messages_analysis;
+    digest.grades_analysis = grades_analysis;
+    digest.homework_analysis = homework_analysis;
+    digest.events_analysis = events_analysis;
 }
 
 fn fallback_announcements(items: &[AnnouncementItem]) -> crate::models::Analysis {
     crate::models::Analysis {
-        urgency: "NORMALNE".to_string(),
+        urgency: Urgency::Normalne,
         summary: format!(
             "Nie udało się wygenerować podsumowania. Liczba ogłoszeń: {}",
             items.len()
         ),
         key_points: items.iter().map(|item| item.title.clone()).collect(),
+        action_items: Vec::new(),
     }
 }
 
 fn fallback_messages(items: &[MessageItem]) -> crate::models::Analysis {
     crate::models::Analysis {
-        urgency: "NORMALNE".to_string(),
+        urgency: Urgency::Normalne,
         summary: format!(
             "Nie udało się wygenerować podsumowania. Liczba wiadomości: {}",
             items.len()
         ),
         key_points: items.iter().map(|item| item.title.clone()).collect(),
+        action_items: Vec::new(),
     }
 }
 
 fn fallback_grades(items: &[GradeItem]) -> crate::models::Analysis {
     crate::models::Analysis {
-        urgency: "NORMALNE".to_string(),
+        urgency: Urgency::Normalne,
         summary: format!(
             "Nie udało się wygenerować podsumowania. Liczba ocen: {}",
             items.len()
@@ -182,12 +674,13 @@ fn fallback_grades(items: &[GradeItem]) -> crate::models::Analysis {
             .iter()
             .map(|item| format!("📚 {} | ⭐ **{}**", item.subject, item.value))
             .collect(),
+        action_items: Vec::new(),
     }
 }
 
 fn fallback_homework(items: &[HomeworkItem]) -> crate::models::Analysis {
     crate::models::Analysis {
-        urgency: "NORMALNE".to_string(),
+        urgency: Urgency::Normalne,
         summary: format!(
             "Nie udało się wygenerować podsumowania. Liczba zadań domowych: {}",
             items.len()
@@ -196,15 +689,32 @@ fn fallback_homework(items: &[HomeworkItem]) -> crate::models::Analysis {
             .iter()
             .map(|item| format!("📝 **{}** - {}", item.subject, item.title))
             .collect(),
+        action_items: Vec::new(),
     }
 }
 
-async fn fetch_new_announcements(client: &Client, db: &Database) -> Result<Vec<AnnouncementItem>> {
+fn fallback_events(items: &[EventItem]) -> crate::models::Analysis {
+    crate::models::Analysis {
+        urgency: Urgency::Normalne,
+        summary: format!(
+            "Nie udało się wygenerować podsumowania. Liczba wydarzeń: {}",
+            items.len()
+        ),
+        key_points: items
+            .iter()
+            .map(|item| format!("📅 **{}** - {}", item.day, item.title))
+            .collect(),
+        action_items: Vec::new(),
+    }
+}
+
+async fn fetch_new_announcements(
+    client: &Client,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<Vec<AnnouncementItem>> {
     let existing_ids = db.existing_announcement_ids()?;
-    let limit = env::var("LIBRUS_ANNOUNCEMENTS_LIMIT")
-        .ok()
-        .and_then(|val| val.parse::<usize>().ok())
-        .unwrap_or(50);
+    let limit = config.announcements_limit;
 
     let notices = match client.school_notices().await {
         Ok(response) => {
@@ -252,7 +762,7 @@ async fn fetch_new_announcements(client: &Client, db: &Database) -> Result<Vec<A
             author: author_name,
         };
 
-        db.save_announcement(&item)?;
+        db.save_announcement(NotifyState::Pending, &item)?;
         new_announcements.push(item);
     }
 
@@ -265,16 +775,14 @@ async fn fetch_new_announcements(client: &Client, db: &Database) -> Result<Vec<A
     Ok(new_announcements)
 }
 
-async fn fetch_new_messages(client: &mut Client, db: &Database) -> Result<Vec<MessageItem>> {
+async fn fetch_new_messages(
+    client: &mut Client,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<Vec<MessageItem>> {
     let existing_ids = db.existing_message_ids()?;
-    let max_pages = env::var("LIBRUS_MESSAGES_MAX_PAGES")
-        .ok()
-        .and_then(|val| val.parse::<u32>().ok())
-        .unwrap_or(10);
-    let limit = env::var("LIBRUS_MESSAGES_PAGE_SIZE")
-        .ok()
-        .and_then(|val| val.parse::<u32>().ok())
-        .unwrap_or(50);
+    let max_pages = config.messages_max_pages;
+    let limit = config.messages_page_size;
 
     let outbox_ids = fetch_outbox_ids(client, max_pages, limit).await?;
     let inbox_messages = fetch_inbox_messages(client, max_pages, limit).await?;
@@ -315,13 +823,16 @@ async fn fetch_new_messages(client: &mut Client, db: &Database) -> Result<Vec<Me
                 message_id = %message.message_id,
                 "Skipping simple acknowledgment message"
             );
-            db.save_message(&MessageItem {
-                id: message.message_id.clone(),
-                title: message.topic.clone(),
-                body: body.clone(),
-                date: message.send_date.clone(),
-                user: message.sender_name.clone(),
-            })?;
+            db.save_message(
+                NotifyState::Skipped,
+                &MessageItem {
+                    id: message.message_id.clone(),
+                    title: message.topic.clone(),
+                    body: body.clone(),
+                    date: message.send_date.clone(),
+                    user: message.sender_name.clone(),
+                },
+            )?;
             continue;
         }
 
@@ -332,7 +843,7 @@ async fn fetch_new_messages(client: &mut Client, db: &Database) -> Result<Vec<Me
             date: message.send_date,
             user: message.sender_name,
         };
-        db.save_message(&item)?;
+        db.save_message(NotifyState::Pending, &item)?;
         new_messages.push(item);
     }
 
@@ -387,18 +898,66 @@ async fn fetch_inbox_messages(
     Ok(all)
 }
 
-async fn fetch_new_grades(client: &Client, db: &Database) -> Result<Vec<GradeItem>> {
-    let existing_ids = db.existing_grade_ids()?;
+/// Returns new grades and previously reported grades that were edited since.
+///
+/// Edits are detected by hashing the grade value with its comments, so a changed value,
+/// a newly attached comment or an edited comment text is reported.
+async fn fetch_new_grades(
+    client: &Client,
+    db: &Database,
+) -> Result<(Vec<GradeItem>, Vec<GradeChange>)> {
+    let stored_grades = db.stored_grades()?;
     let response = client.grades().await?;
 
     let mut subject_cache: HashMap<i32, String> = HashMap::new();
     let mut new_grades = Vec::new();
+    let mut changed_grades = Vec::new();
 
     for grade in response.grades {
         let grade_id = grade.id.to_string();
-        if existing_ids.contains(&grade_id) {
+        let mut comments = Vec::new();
+        let mut complete = true;
+        for comment_ref in grade.comments.iter().flatten() {
+            match client.grade_comment(comment_ref.id).await {
+                Ok(comment) => comments.push((
+                    comment_ref.id.to_string(),
+                    comment
+                        .comment
+                        .map(|comment| comment.text)
+                        .unwrap_or_default(),
+                )),
+                Err(e) => {
+                    tracing::warn!(
+                        grade_id = %grade_id,
+                        error = %e,
+                        "Failed to fetch grade comment, checking the grade next run"
+                    );
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        // A missing comment would change the hash and look like an edit
+        if !complete {
             continue;
         }
+        let mut hash_parts = vec![grade.grade.as_str()];
+        for (id, text) in &comments {
+            hash_parts.extend([id.as_str(), text.as_str()]);
+        }
+        let content_hash = text_utils::content_hash(&hash_parts);
+
+        let previous = stored_grades.get(&grade_id);
+        if let Some(previous) = previous {
+            if previous.content_hash == content_hash {
+                continue;
+            }
+            if previous.content_hash.is_empty() {
+                // Stored before hashing existed; take the current state as the baseline
+                db.set_grade_hash(&grade_id, &content_hash)?;
+                continue;
+            }
+        }
 
         let subject_id = grade.subject.id;
         let subject_name = if let Some(name) = subject_cache.get(&subject_id) {
@@ -415,25 +974,32 @@ async fn fetch_new_grades(client: &Client, db: &Database) -> Result<Vec<GradeIte
             name
         };
 
-        let mut info = String::new();
-        if let Some(comments) = grade.comments.as_ref() {
-            if let Some(comment_ref) = comments.first() {
-                if let Ok(comment) = client.grade_comment(comment_ref.id).await {
-                    if let Some(comment) = comment.comment {
-                        info = comment.text;
-                    }
-                }
-            }
-        }
+        let info = comments
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
 
         let item = GradeItem {
             id: grade_id,
             subject: subject_name,
             value: grade.grade,
             info,
+            content_hash,
         };
 
-        db.save_grade(&item)?;
+        // Edited grades are stored once the change has been delivered
+        if let Some(previous) = previous {
+            changed_grades.push(GradeChange {
+                grade: item,
+                previous_value: previous.value.clone(),
+                previous_info: previous.info.clone(),
+            });
+            continue;
+        }
+
+        db.save_grade(NotifyState::Pending, &item)?;
         new_grades.push(item);
     }
 
@@ -442,8 +1008,288 @@ async fn fetch_new_grades(client: &Client, db: &Database) -> Result<Vec<GradeIte
     } else {
         tracing::info!(count = new_grades.len(), "Found new grades");
     }
+    if !changed_grades.is_empty() {
+        tracing::info!(count = changed_grades.len(), "Found edited grades");
+    }
+
+    Ok((new_grades, changed_grades))
+}
+
+async fn fetch_new_events(client: &Client, db: &Database) -> Result<Vec<EventItem>> {
+    let existing_ids = db.existing_event_ids()?;
+    let response = match client.calendar_events().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to fetch calendar events, skipping events");
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut category_cache: HashMap<i32, String> = HashMap::new();
+    let mut subject_cache: HashMap<i32, String> = HashMap::new();
+    let mut new_events = Vec::new();
 
-    Ok(new_grades)
+    for event in response.events {
+        let event_id = event.id.to_string();
+        if existing_ids.contains(&event_id) {
+            continue;
+        }
+
+        let category_name = if let Some(category_ref) = event.category.as_ref() {
+            let category_id = category_ref.id as i32;
+            if let Some(name) = category_cache.get(&category_id) {
+                name.clone()
+            } else {
+                let name = client
+                    .event_category(category_id)
+                    .await
+                    .ok()
+                    .and_then(|resp| resp.category)
+                    .map(|category| category.name)
+                    .unwrap_or_else(|| "Wydarzenie".to_string());
+                category_cache.insert(category_id, name.clone());
+                name
+            }
+        } else {
+            "Wydarzenie".to_string()
+        };
+
+        let subject_name = if let Some(subject_ref) = event.subject.as_ref() {
+            let subject_id = subject_ref.id as i32;
+            if let Some(name) = subject_cache.get(&subject_id) {
+                Some(name.clone())
+            } else {
+                let name = client
+                    .subject(subject_id)
+                    .await
+                    .ok()
+                    .and_then(|resp| resp.subject)
+                    .map(|subject| subject.name);
+                if let Some(name) = name.as_ref() {
+                    subject_cache.insert(subject_id, name.clone());
+                }
+                name
+            }
+        } else {
+            None
+        };
+
+        let title = match subject_name {
+            Some(subject) => format!("{} — {}", category_name, subject),
+            None => category_name,
+        };
+
+        let mut description = event.content.trim().to_string();
+        if let Some(time_from) = event.time_from.as_ref().filter(|t| !t.is_empty()) {
+            let hours = match event.time_to.as_ref().filter(|t| !t.is_empty()) {
+                Some(time_to) => format!("{} - {}", time_from, time_to),
+                None => time_from.clone(),
+            };
+            description = format!("Godziny: {}\n{}", hours, description);
+        }
+
+        let item = EventItem {
+            id: event_id,
+            title,
+            day: event.date,
+            description,
+        };
+
+        // Past calendar entries are recorded but not reported
+        if !text_utils::is_within_days(&item.day, 0) {
+            db.save_event(NotifyState::Skipped, &item)?;
+            continue;
+        }
+        db.save_event(NotifyState::Pending, &item)?;
+        new_events.push(item);
+    }
+
+    if new_events.is_empty() {
+        tracing::info!("No new events");
+    } else {
+        tracing::info!(count = new_events.len(), "Found new calendar events");
+    }
+
+    Ok(new_events)
+}
+
+async fn fetch_new_attendance(
+    client: &Client,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<Vec<AttendanceItem>> {
+    let existing_ids = db.existing_attendance_ids()?;
+    let max_age_days = config.attendance_days;
+
+    let response = match client.attendances().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to fetch attendance, skipping");
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut type_cache: HashMap<i32, (String, String)> = HashMap::new();
+    let mut subject_cache: HashMap<i32, String> = HashMap::new();
+    let mut new_attendance = Vec::new();
+
+    for attendance in response.attendances {
+        if existing_ids.contains(&attendance.id) {
+            continue;
+        }
+
+        let type_id = attendance.attendance_type.id;
+        let (kind, short) = if let Some(cached) = type_cache.get(&type_id) {
+            cached.clone()
+        } else {
+            let resolved = client
+                .attendance_type(type_id)
+                .await
+                .ok()
+                .and_then(|resp| resp.attendance_type)
+                .map(|t| (t.name, t.short))
+                .unwrap_or_else(|| ("Nieznany".to_string(), String::new()));
+            type_cache.insert(type_id, resolved.clone());
+            resolved
+        };
+
+        let item = AttendanceItem {
+            id: attendance.id,
+            date: attendance.date,
+            lesson_no: attendance.lesson_no,
+            subject: String::new(),
+            kind,
+            short,
+        };
+
+        // Presences and already excused entries are only recorded so they are not re-checked
+        let reportable = (item.is_unexcused_absence() || item.is_late())
+            && text_utils::is_within_days(&item.date, max_age_days);
+        if !reportable {
+            db.save_attendance(NotifyState::Skipped, &item)?;
+            continue;
+        }
+
+        let lesson_id = attendance.lesson.id;
+        let subject_name = if let Some(name) = subject_cache.get(&lesson_id) {
+            name.clone()
+        } else {
+            let subject_id = client
+                .lesson(lesson_id)
+                .await
+                .ok()
+                .and_then(|resp| resp.lesson)
+                .map(|lesson| lesson.subject.id);
+            let name = match subject_id {
+                Some(subject_id) => client
+                    .subject(subject_id)
+                    .await
+                    .ok()
+                    .and_then(|resp| resp.subject)
+                    .map(|subject| subject.name)
+                    .unwrap_or_else(|| "Nieznany".to_string()),
+                None => "Nieznany".to_string(),
+            };
+            subject_cache.insert(lesson_id, name.clone());
+            name
+        };
+
+        let item = AttendanceItem {
+            subject: subject_name,
+            ..item
+        };
+
+        db.save_attendance(NotifyState::Pending, &item)?;
+        new_attendance.push(item);
+    }
+
+    if new_attendance.is_empty() {
+        tracing::info!("No new unexcused absences or late arrivals");
+    } else {
+        tracing::info!(
+            count = new_attendance.len(),
+            "Found new unexcused absences or late arrivals"
+        );
+    }
+
+    Ok(new_attendance)
+}
+
+/// Returns the plan changes against the stored snapshot, and the new snapshot to store
+/// once they were delivered. Without changes the snapshot is stored right away.
+async fn fetch_timetable_changes(
+    client: &Client,
+    db: &Database,
+    config: &LibrusConfig,
+) -> Result<(Vec<TimetableChange>, TimetableSnapshot)> {
+    let days_ahead = config.timetable_days;
+
+    let today = Local::now().date_naive();
+    let last_day = today + Duration::days(days_ahead);
+    let mut week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+
+    let mut current = Vec::new();
+    while week_start <= last_day {
+        let response = match client
+            .timetable(&week_start.format("%Y-%m-%d").to_string())
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to fetch timetable, skipping plan changes");
+                return Ok((Vec::new(), TimetableSnapshot::default()));
+            }
+        };
+
+        for (date, slots) in response.timetable {
+            for entry in slots.into_iter().flatten() {
+                current.push(TimetableLesson {
+                    date: date.clone(),
+                    lesson_no: entry.lesson_no,
+                    hour_from: entry.hour_from,
+                    hour_to: entry.hour_to,
+                    subject: entry
+                        .subject
+                        .map(|subject| subject.name)
+                        .unwrap_or_else(|| "Nieznany".to_string()),
+                    teacher: entry
+                        .teacher
+                        .map(|teacher| format!("{} {}", teacher.first_name, teacher.last_name))
+                        .unwrap_or_default(),
+                    canceled: entry.is_canceled,
+                    substitution: entry.is_substitution_class,
+                });
+            }
+        }
+        week_start += Duration::days(7);
+    }
+
+    let date_from = today.format("%Y-%m-%d").to_string();
+    let date_to = last_day.format("%Y-%m-%d").to_string();
+    current.retain(|lesson| lesson.date >= date_from && lesson.date <= date_to);
+
+    let dates: Vec<String> = (0..=days_ahead)
+        .map(|offset| {
+            (today + Duration::days(offset))
+                .format("%Y-%m-%d")
+                .to_string()
+        })
+        .collect();
+
+    let previous = db.timetable_snapshot(&date_from, &date_to)?;
+    let changes = timetable::diff_timetable(&previous, &current, &dates);
+    let snapshot = TimetableSnapshot {
+        dates,
+        lessons: current,
+    };
+
+    if changes.is_empty() {
+        tracing::info!("No timetable changes");
+        db.replace_timetable_snapshot(&snapshot)?;
+        return Ok((changes, TimetableSnapshot::default()));
+    }
+    tracing::info!(count = changes.len(), "Found timetable changes");
+    Ok((changes, snapshot))
 }
 
 async fn fetch_new_homeworks(client: &Client, db: &Database) -> Result<Vec<HomeworkItem>> {
@@ -508,7 +1354,7 @@ async fn fetch_new_homeworks(client: &Client, db: &Database) -> Result<Vec<Homew
             user: teacher_name,
         };
 
-        db.save_homework(&item)?;
+        db.save_homework(NotifyState::Pending, &item)?;
         new_homeworks.push(item);
     }
 
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::config::MatrixConfig;
+use crate::models::Digest;
+use crate::notifier::{render_markdown, Notifier, LIBRUS_URL};
+use crate::template::{format_markdown, plain_markdown};
+
+const HTML_FORMAT: &str = "org.matrix.custom.html";
+
+/// Sends one `m.text` message per student to a Matrix room, as HTML with a plain-text
+/// fallback.
+pub struct MatrixNotifier {
+    http: reqwest::Client,
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RoomMessage<'a> {
+    msgtype: &'a str,
+    body: &'a str,
+    format: &'a str,
+    formatted_body: &'a str,
+}
+
+/// The Librus link `render_markdown` ends with; model output may contain other
+/// `[text](url)` spans, which stay plain text.
+fn librus_link() -> String {
+    format!("[Otwórz Librusa]({})", LIBRUS_URL)
+}
+
+/// The Markdown digest as escaped HTML with bold spans, the Librus link and line breaks.
+fn html_body(markdown: &str) -> String {
+    format_markdown(markdown)
+        .replace(
+            &librus_link(),
+            &format!(r#"<a href="{}">Otwórz Librusa</a>"#, LIBRUS_URL),
+        )
+        .replace('\n', "<br>")
+}
+
+/// The Markdown digest without markers, for clients that do not render HTML.
+fn plain_body(markdown: &str) -> String {
+    plain_markdown(markdown).replace(&librus_link(), &format!("Otwórz Librusa: {}", LIBRUS_URL))
+}
+
+impl MatrixNotifier {
+    pub fn from_config(config: &MatrixConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            homeserver: config.homeserver.trim_end_matches('/').to_string(),
+            access_token: config.access_token.clone(),
+            room_id: config.room_id.clone(),
+        }
+    }
+
+    /// `PUT /rooms/{roomId}/send/m.room.message/{txnId}`, with the room id encoded as
+    /// a single path segment.
+    fn send_url(&self, txn_id: &str) -> Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(&self.homeserver).context("Invalid MATRIX_HOMESERVER")?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid MATRIX_HOMESERVER"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room_id,
+                "send",
+                "m.room.message",
+                txn_id,
+            ]);
+        Ok(url)
+    }
+}
+
+#[async_trait]
+impl Notifier for MatrixNotifier {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        let run_id = Utc::now().timestamp_millis();
+        for (idx, digest) in digests.iter().enumerate() {
+            let markdown = render_markdown(digest);
+            let body = plain_body(&markdown);
+            let formatted_body = html_body(&markdown);
+            let message = RoomMessage {
+                msgtype: "m.text",
+                body: &body,
+                format: HTML_FORMAT,
+                formatted_body: &formatted_body,
+            };
+            let resp = self
+                .http
+                .put(self.send_url(&format!("librus-{}-{}", run_id, idx))?)
+                .bearer_auth(&self.access_token)
+                .json(&message)
+                .send()
+                .await
+                .context("Matrix request failed")?;
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(anyhow!("Matrix API error: {} - {}", status, body));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server;
+
+    #[tokio::test]
+    async fn test_sends_to_escaped_room() {
+        let (homeserver, request) = stub_server(r#"{"event_id": "$1"}"#.to_string()).await;
+        let notifier = MatrixNotifier {
+            http: reqwest::Client::new(),
+            homeserver,
+            access_token: "token".to_string(),
+            room_id: "!abc:example.org".to_string(),
+        };
+        let digest = Digest {
+            student: "Emilka".to_string(),
+            ..Digest::default()
+        };
+        notifier.notify(&[&digest]).await.unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with(
+            "PUT /_matrix/client/v3/rooms/!abc:example.org/send/m.room.message/librus-"
+        ));
+        assert!(request.contains("\"msgtype\":\"m.text\""));
+        assert!(request.contains("\"format\":\"org.matrix.custom.html\""));
+        assert!(request.contains("<strong>Emilka</strong>"));
+        assert!(!request.contains("\"body\":\"**"));
+    }
+
+    #[test]
+    fn test_html_and_plain_bodies() {
+        let markdown = format!(
+            "**Emilka** <b> [kliknij](javascript:alert(1))\n- [Otwórz Librusa]({})",
+            LIBRUS_URL
+        );
+        assert_eq!(
+            html_body(&markdown),
+            format!(
+                "<strong>Emilka</strong> &lt;b&gt; [kliknij](javascript:alert(1))<br>- <a href=\"{}\">Otwórz Librusa</a>",
+                LIBRUS_URL
+            )
+        );
+        assert_eq!(
+            plain_body(&markdown),
+            format!(
+                "Emilka <b> [kliknij](javascript:alert(1))\n- Otwórz Librusa: {}",
+                LIBRUS_URL
+            )
+        );
+    }
+}
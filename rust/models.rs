@@ -1,6 +1,13 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone)]
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use crate::text_utils::content_hash;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageItem {
     pub id: String,
     pub title: String,
@@ -9,7 +16,7 @@ pub struct MessageItem {
     pub user: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnnouncementItem {
     pub id: String,
     pub title: String,
@@ -18,15 +25,49 @@ pub struct AnnouncementItem {
     pub author: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GradeItem {
     pub id: String,
     pub subject: String,
     pub value: String,
     pub info: String,
+    /// Hash of the grade value and the id and text of each comment, see
+    /// `text_utils::content_hash`.
+    pub content_hash: String,
 }
 
-#[derive(Debug, Clone)]
+/// A previously reported grade that was edited in Librus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradeChange {
+    pub grade: GradeItem,
+    pub previous_value: String,
+    pub previous_info: String,
+}
+
+impl GradeChange {
+    /// Short description of what changed, e.g. "+/- → +, nowy komentarz: Poprawa".
+    pub fn details(&self) -> String {
+        let mut parts = Vec::new();
+        if self.previous_value != self.grade.value {
+            parts.push(format!("{} → {}", self.previous_value, self.grade.value));
+        }
+        if self.previous_info != self.grade.info {
+            if self.grade.info.is_empty() {
+                parts.push("usunięty komentarz".to_string());
+            } else if self.previous_info.is_empty() {
+                parts.push(format!("nowy komentarz: {}", self.grade.info));
+            } else {
+                parts.push(format!("zmieniony komentarz: {}", self.grade.info));
+            }
+        }
+        if parts.is_empty() {
+            parts.push(format!("{} (zmiana w Librusie)", self.grade.value));
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HomeworkItem {
     pub id: String,
     pub subject: String,
@@ -38,10 +79,477 @@ pub struct HomeworkItem {
     pub user: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventItem {
+    pub id: String,
+    pub title: String,
+    pub day: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttendanceItem {
+    pub id: String,
+    pub date: String,
+    pub lesson_no: i32,
+    pub subject: String,
+    pub kind: String,
+    pub short: String,
+}
+
+impl AttendanceItem {
+    pub fn is_unexcused_absence(&self) -> bool {
+        self.short == "nb"
+    }
+
+    pub fn is_late(&self) -> bool {
+        self.short == "sp"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimetableLesson {
+    pub date: String,
+    pub lesson_no: String,
+    pub hour_from: String,
+    pub hour_to: String,
+    pub subject: String,
+    pub teacher: String,
+    pub canceled: bool,
+    pub substitution: bool,
+}
+
+/// The freshly fetched plan of `dates`, replacing the stored snapshot of those days.
+#[derive(Debug, Clone, Default)]
+pub struct TimetableSnapshot {
+    pub dates: Vec<String>,
+    pub lessons: Vec<TimetableLesson>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimetableChange {
+    pub date: String,
+    pub lesson_no: String,
+    pub hour_from: String,
+    pub kind: String,
+    pub subject: String,
+    pub details: String,
+}
+
+/// What an action item asks the parent to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Payment,
+    Supplies,
+    Reading,
+    Meeting,
+    Test,
+    #[serde(other)]
+    Other,
+}
+
+impl ActionKind {
+    pub const ALL: [ActionKind; 6] = [
+        ActionKind::Payment,
+        ActionKind::Supplies,
+        ActionKind::Reading,
+        ActionKind::Meeting,
+        ActionKind::Test,
+        ActionKind::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ActionKind::Payment => "payment",
+            ActionKind::Supplies => "supplies",
+            ActionKind::Reading => "reading",
+            ActionKind::Meeting => "meeting",
+            ActionKind::Test => "test",
+            ActionKind::Other => "other",
+        }
+    }
+
+    /// Reads the value stored by [`ActionKind::as_str`].
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "payment" => ActionKind::Payment,
+            "supplies" => ActionKind::Supplies,
+            "reading" => ActionKind::Reading,
+            "meeting" => ActionKind::Meeting,
+            "test" => ActionKind::Test,
+            _ => ActionKind::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ActionKind::Payment => "💰 Wpłata",
+            ActionKind::Supplies => "🎒 Przybory",
+            ActionKind::Reading => "📖 Czytanie",
+            ActionKind::Meeting => "👥 Spotkanie",
+            ActionKind::Test => "📝 Sprawdzian",
+            ActionKind::Other => "📌 Inne",
+        }
+    }
+}
+
+/// A concrete to-do the model extracted, e.g. a payment or a test to prepare for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionItem {
+    pub kind: ActionKind,
+    /// `YYYY-MM-DD`, when the item names a date.
+    #[serde(default)]
+    pub due: Option<String>,
+    #[serde(default)]
+    pub amount_pln: Option<f64>,
+    pub description: String,
+    /// Librus id of the message, announcement, homework or event it comes from.
+    #[serde(default, deserialize_with = "lenient_id")]
+    pub source_id: Option<String>,
+}
+
+impl ActionItem {
+    /// Identifies the same item when a later run extracts it again from the `source`
+    /// section.
+    ///
+    /// The model rephrases descriptions between runs, so an item naming the Librus
+    /// element it comes from is keyed on that element instead of its description.
+    pub fn key(&self, source: &str) -> String {
+        let due = self.due.as_deref().unwrap_or_default();
+        match &self.source_id {
+            Some(source_id) => content_hash(&[source, source_id, self.kind.as_str(), due]),
+            None => content_hash(&[self.kind.as_str(), due, &self.description]),
+        }
+    }
+}
+
+/// Accepts ids the model returns as numbers as well as strings.
+fn lenient_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(id)) => Some(id),
+            Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+            _ => None,
+        },
+    )
+}
+
+/// An action item stored in the `tasks` table.
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub id: i64,
+    pub item: ActionItem,
+    /// Section the item was extracted from, e.g. `messages`.
+    pub source: String,
+    pub done_at: Option<String>,
+}
+
+/// How soon the parent has to act; ordered, so `Urgency::Pilne` is the maximum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Urgency {
+    Niepilne,
+    #[default]
+    Normalne,
+    Pilne,
+}
+
+impl Urgency {
+    /// Most urgent first.
+    pub const ALL: [Urgency; 3] = [Urgency::Pilne, Urgency::Normalne, Urgency::Niepilne];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Urgency::Pilne => "PILNE",
+            Urgency::Normalne => "NORMALNE",
+            Urgency::Niepilne => "NIEPILNE",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Urgency::Pilne => "Pilne",
+            Urgency::Normalne => "Normalne",
+            Urgency::Niepilne => "Niepilne",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            Urgency::Pilne => "🔴",
+            Urgency::Normalne => "🟡",
+            Urgency::Niepilne => "🟢",
+        }
+    }
+
+    /// Accepts the spellings models and people use: "pilne", "Nie pilne", "normalna".
+    pub fn parse(urgency: &str) -> Option<Self> {
+        let compact: String = urgency
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .flat_map(char::to_uppercase)
+            .collect();
+        if compact.starts_with("NIEPILN") {
+            Some(Urgency::Niepilne)
+        } else if compact.starts_with("PILN") {
+            Some(Urgency::Pilne)
+        } else if compact.starts_with("NORMALN") {
+            Some(Urgency::Normalne)
+        } else {
+            None
+        }
+    }
+
+    /// Only urgent items are worth an instant push; the rest can wait for a digest.
+    pub fn is_instant(self) -> bool {
+        self == Urgency::Pilne
+    }
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Urgency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let urgency = String::deserialize(deserializer)?;
+        Urgency::parse(&urgency).ok_or_else(|| {
+            de::Error::custom(format!(
+                "unknown urgency `{}`, expected PILNE, NORMALNE or NIEPILNE",
+                urgency
+            ))
+        })
+    }
+}
+
+/// Falls back to NORMALNE instead of rejecting a model reply over an odd urgency.
+fn lenient_urgency<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Urgency, D::Error> {
+    let urgency = String::deserialize(deserializer)?;
+    Ok(Urgency::parse(&urgency).unwrap_or_else(|| {
+        tracing::warn!(%urgency, "Unknown urgency in LLM reply, using NORMALNE");
+        Urgency::Normalne
+    }))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Analysis {
-    pub urgency: String,
+    #[serde(default, deserialize_with = "lenient_urgency")]
+    pub urgency: Urgency,
+    #[serde(default)]
     pub summary: String,
-    #[serde(rename = "keyPoints")]
+    #[serde(default, rename = "keyPoints")]
     pub key_points: Vec<String>,
+    #[serde(default, rename = "actionItems")]
+    pub action_items: Vec<ActionItem>,
+}
+
+impl Analysis {
+    /// JSON schema of the model reply, in the strict form of OpenAI structured outputs:
+    /// every property is required and optional values are nullable instead.
+    pub fn json_schema() -> Value {
+        let kinds: Vec<&str> = ActionKind::ALL.iter().map(|kind| kind.as_str()).collect();
+        let urgencies: Vec<&str> = Urgency::ALL
+            .iter()
+            .map(|urgency| urgency.as_str())
+            .collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "urgency": {"type": "string", "enum": urgencies},
+                "summary": {"type": "string"},
+                "keyPoints": {"type": "array", "items": {"type": "string"}},
+                "actionItems": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "kind": {"type": "string", "enum": kinds},
+                            "due": {"type": ["string", "null"], "description": "YYYY-MM-DD"},
+                            "amountPln": {"type": ["number", "null"]},
+                            "description": {"type": "string"},
+                            "sourceId": {"type": ["string", "null"]}
+                        },
+                        "required": ["kind", "due", "amountPln", "description", "sourceId"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["urgency", "summary", "keyPoints", "actionItems"],
+            "additionalProperties": false
+        })
+    }
+
+    /// Cleans up a model reply: trims text, drops empty key points and action items,
+    /// and clears dates and amounts that make no sense. Fails when nothing usable is
+    /// left. Odd urgency spellings are already handled while deserializing.
+    pub fn normalize(mut self) -> Result<Self> {
+        self.summary = self.summary.trim().to_string();
+        self.key_points = self
+            .key_points
+            .into_iter()
+            .map(|point| point.trim().to_string())
+            .filter(|point| !point.is_empty())
+            .collect();
+        if self.summary.is_empty() && self.key_points.is_empty() {
+            bail!("LLM reply has neither a summary nor key points");
+        }
+
+        self.action_items.retain_mut(|item| {
+            item.description = item.description.trim().to_string();
+            item.due = item.due.take().and_then(|due| {
+                Some(
+                    NaiveDate::parse_from_str(due.trim().get(..10)?, "%Y-%m-%d")
+                        .ok()?
+                        .to_string(),
+                )
+            });
+            item.amount_pln = item
+                .amount_pln
+                .filter(|amount| amount.is_finite() && *amount > 0.0);
+            item.source_id = item.source_id.take().filter(|id| !id.trim().is_empty());
+            !item.description.is_empty()
+        });
+        Ok(self)
+    }
+}
+
+/// Stored homework and events falling due soon, reminded once per due date.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reminders {
+    /// Due date (`YYYY-MM-DD`) the reminders are for; empty when reminders are off.
+    pub due: String,
+    /// Heading such as "Jutro" or "Za 3 dni".
+    pub label: String,
+    pub homework: Vec<HomeworkItem>,
+    pub events: Vec<EventItem>,
+}
+
+impl Reminders {
+    pub fn is_empty(&self) -> bool {
+        self.homework.is_empty() && self.events.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Digest {
+    /// Id of the Librus account, see `Account::id`.
+    pub account: String,
+    pub student: String,
+    pub announcements_analysis: Option<Analysis>,
+    pub messages_analysis: Option<Analysis>,
+    pub grades_analysis: Option<Analysis>,
+    pub homework_analysis: Option<Analysis>,
+    pub events_analysis: Option<Analysis>,
+    pub announcements: Vec<AnnouncementItem>,
+    pub messages: Vec<MessageItem>,
+    pub grades: Vec<GradeItem>,
+    pub grade_changes: Vec<GradeChange>,
+    pub homework: Vec<HomeworkItem>,
+    pub events: Vec<EventItem>,
+    pub attendance: Vec<AttendanceItem>,
+    pub timetable_changes: Vec<TimetableChange>,
+    /// Stored once `timetable_changes` were delivered, so undelivered changes are
+    /// found again against the old snapshot.
+    #[serde(skip)]
+    pub timetable_snapshot: TimetableSnapshot,
+    pub reminders: Reminders,
+}
+
+impl Digest {
+    /// True when nothing new was fetched and nothing is due for a reminder.
+    pub fn is_empty(&self) -> bool {
+        self.announcements.is_empty()
+            && self.messages.is_empty()
+            && self.grades.is_empty()
+            && self.grade_changes.is_empty()
+            && self.homework.is_empty()
+            && self.events.is_empty()
+            && self.attendance.is_empty()
+            && self.timetable_changes.is_empty()
+            && self.reminders.is_empty()
+    }
+
+    /// The analyses with the section they came from, as stored in `tasks.source`.
+    pub fn analyses_by_source(&self) -> [(&'static str, &Option<Analysis>); 5] {
+        [
+            ("announcements", &self.announcements_analysis),
+            ("messages", &self.messages_analysis),
+            ("grades", &self.grades_analysis),
+            ("homework", &self.homework_analysis),
+            ("events", &self.events_analysis),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis_normalize() {
+        let analysis: Analysis = serde_json::from_str(
+            r#"{"urgency": " pilne ", "summary": " Wycieczka ", "actionItems": [
+                {"kind": "payment", "due": "2025-03-14T00:00:00", "amountPln": -5, "description": " Wpłata ", "sourceId": ""},
+                {"kind": "test", "due": "w piątek", "description": "  "}
+            ]}"#,
+        )
+        .unwrap();
+        let analysis = analysis.normalize().unwrap();
+        assert_eq!(analysis.urgency, Urgency::Pilne);
+        assert_eq!(analysis.summary, "Wycieczka");
+        assert!(analysis.key_points.is_empty());
+        assert_eq!(analysis.action_items.len(), 1);
+        let item = &analysis.action_items[0];
+        assert_eq!(item.description, "Wpłata");
+        assert_eq!(item.due.as_deref(), Some("2025-03-14"));
+        assert_eq!(item.amount_pln, None);
+        assert_eq!(item.source_id, None);
+
+        assert_eq!(Urgency::parse("Nie pilne"), Some(Urgency::Niepilne));
+        assert_eq!(Urgency::parse("normalna"), Some(Urgency::Normalne));
+        assert_eq!(Urgency::parse("HIGH"), None);
+        let odd: Analysis = serde_json::from_str(r#"{"urgency": "HIGH", "summary": "x"}"#).unwrap();
+        assert_eq!(odd.urgency, Urgency::Normalne);
+        assert!(serde_json::from_str::<Urgency>(r#""HIGH""#).is_err());
+        assert!(Urgency::Pilne > Urgency::Normalne && Urgency::Normalne > Urgency::Niepilne);
+        assert_eq!(
+            serde_json::to_string(&Urgency::Niepilne).unwrap(),
+            r#""NIEPILNE""#
+        );
+
+        let empty: Analysis =
+            serde_json::from_str(r#"{"urgency": "PILNE", "summary": " "}"#).unwrap();
+        assert!(empty.normalize().is_err());
+    }
+
+    fn change(previous_value: &str, value: &str, previous_info: &str, info: &str) -> GradeChange {
+        GradeChange {
+            grade: GradeItem {
+                id: "1".to_string(),
+                subject: "Matematyka".to_string(),
+                value: value.to_string(),
+                info: info.to_string(),
+                content_hash: String::new(),
+            },
+            previous_value: previous_value.to_string(),
+            previous_info: previous_info.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_grade_change_details() {
+        assert_eq!(change("+/-", "+", "", "").details(), "+/- → +");
+        assert_eq!(
+            change("4", "4", "", "Poprawa").details(),
+            "nowy komentarz: Poprawa"
+        );
+        assert_eq!(
+            change("3", "4", "Kartkówka", "Poprawa").details(),
+            "3 → 4, zmieniony komentarz: Poprawa"
+        );
+    }
 }
@@ -0,0 +1,418 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::config::{Channel, NotifyConfig};
+use crate::email::{has_content, max_urgency, EmailNotifier};
+use crate::matrix::MatrixNotifier;
+use crate::models::{Analysis, Digest};
+use crate::ntfy::NtfyNotifier;
+use crate::telegram::TelegramClient;
+use crate::webhook::{ChatWebhookNotifier, WebhookNotifier};
+
+pub const LIBRUS_URL: &str = "https://synergia.librus.pl/uczen/index";
+
+/// A delivery channel for the digests of one run.
+///
+/// Every notifier receives only digests that have something to report.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Channel name as used in `NOTIFY_CHANNELS`.
+    fn name(&self) -> &str;
+
+    /// Push channels interrupt whoever reads them. Next to a digest channel such as
+    /// email they only get urgent digests, see
+    /// [`Urgency::is_instant`](crate::models::Urgency::is_instant).
+    fn is_push(&self) -> bool {
+        true
+    }
+
+    /// False for a channel that only prints what it would send, such as email with
+    /// sending disabled; items it handled stay pending.
+    fn delivers(&self) -> bool {
+        true
+    }
+
+    /// True when every digest goes out as a message of its own; each is then passed
+    /// alone, so a failure on one leaves the others delivered.
+    fn sends_each_digest(&self) -> bool {
+        false
+    }
+
+    async fn notify(&self, digests: &[&Digest]) -> Result<()>;
+}
+
+/// Builds the channels listed in `notify.channels` (`NOTIFY_CHANNELS`).
+pub fn notifiers_from_config(config: &NotifyConfig) -> Vec<Box<dyn Notifier>> {
+    config
+        .channels
+        .iter()
+        .map(|channel| -> Box<dyn Notifier> {
+            match channel {
+                Channel::Email => Box::new(EmailNotifier::from_config(&config.email)),
+                Channel::Telegram => Box::new(TelegramClient::from_config(&config.telegram)),
+                Channel::Webhook => Box::new(WebhookNotifier::from_config(&config.webhook)),
+                Channel::Ntfy => Box::new(NtfyNotifier::from_config(&config.ntfy)),
+                Channel::Matrix => Box::new(MatrixNotifier::from_config(&config.matrix)),
+                Channel::Discord => Box::new(ChatWebhookNotifier::discord(&config.discord)),
+                Channel::Slack => Box::new(ChatWebhookNotifier::slack(&config.slack)),
+            }
+        })
+        .collect()
+}
+
+/// Outcome of [`notify_all`].
+pub struct Notified {
+    /// Per input digest, whether it reached a channel that sends and no channel failed
+    /// on it; digests with nothing to report count as delivered.
+    pub delivered: Vec<bool>,
+    /// The first channel failure, or the first digest no channel sent.
+    pub error: Option<anyhow::Error>,
+}
+
+/// Delivers the digests through every channel; a failing channel does not stop the others.
+///
+/// When a digest channel that actually sends is configured, push channels only get the
+/// digests with something urgent and the digest channel carries the rest. A digest
+/// counts as delivered when no channel failed on it and it reached a channel that
+/// sends; the others stay pending and the next run sends them again, also through the
+/// channels that succeeded.
+pub async fn notify_all(notifiers: &[Box<dyn Notifier>], digests: &[Digest]) -> Notified {
+    let pending: Vec<usize> = (0..digests.len())
+        .filter(|&idx| has_content(&digests[idx]))
+        .collect();
+    let mut reached: Vec<bool> = digests.iter().map(|digest| !has_content(digest)).collect();
+    let mut failed = vec![false; digests.len()];
+    if pending.is_empty() {
+        tracing::info!("No new items to notify about");
+        return Notified {
+            delivered: reached,
+            error: None,
+        };
+    }
+
+    let has_digest_channel = notifiers
+        .iter()
+        .any(|notifier| !notifier.is_push() && notifier.delivers());
+    let mut errors = Vec::new();
+    for notifier in notifiers {
+        let selected: Vec<usize> = pending
+            .iter()
+            .copied()
+            .filter(|&idx| {
+                !(notifier.is_push() && has_digest_channel)
+                    || max_urgency(&[&digests[idx]]).is_instant()
+            })
+            .collect();
+        if selected.is_empty() {
+            tracing::info!(channel = notifier.name(), "Nothing urgent to push");
+            continue;
+        }
+        let batches: Vec<Vec<usize>> = if notifier.sends_each_digest() {
+            selected.into_iter().map(|idx| vec![idx]).collect()
+        } else {
+            vec![selected]
+        };
+        for batch in batches {
+            let batch_digests: Vec<&Digest> = batch.iter().map(|&idx| &digests[idx]).collect();
+            match notifier.notify(&batch_digests).await {
+                Ok(()) if !notifier.delivers() => {
+                    tracing::info!(channel = notifier.name(), "Notification only printed");
+                }
+                Ok(()) => {
+                    tracing::info!(channel = notifier.name(), "Notification delivered");
+                    for idx in batch {
+                        reached[idx] = true;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        channel = notifier.name(),
+                        error = %err,
+                        "Notification channel failed"
+                    );
+                    for idx in batch {
+                        failed[idx] = true;
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+    }
+
+    let mut error = (!errors.is_empty()).then(|| errors.remove(0));
+    if let Some(idx) = reached.iter().position(|reached| !reached) {
+        tracing::warn!(
+            student = %digests[idx].student,
+            "Digest reached no channel that sends, items stay pending"
+        );
+        error.get_or_insert_with(|| {
+            anyhow!(
+                "digest for {} was not sent by any channel",
+                digests[idx].student
+            )
+        });
+    }
+    let delivered = reached
+        .iter()
+        .zip(&failed)
+        .map(|(reached, failed)| *reached && !failed)
+        .collect();
+    Notified { delivered, error }
+}
+
+fn render_analysis(icon: &str, title: &str, analysis: &Analysis) -> String {
+    let mut section = format!(
+        "\n\n{} **{}** ({})\n{}",
+        icon, title, analysis.urgency, analysis.summary
+    );
+    for point in &analysis.key_points {
+        section.push_str(&format!("\n- {}", point));
+    }
+    section
+}
+
+/// Compact Markdown rendering of a digest for chat-style channels.
+///
+/// Model output already uses `**bold**`, so it is passed through unchanged.
+pub fn render_markdown(digest: &Digest) -> String {
+    let mut text = format!("**{}** [{}]", digest.student, max_urgency(&[digest]));
+
+    let reminders = &digest.reminders;
+    if !reminders.is_empty() {
+        text.push_str(&format!(
+            "\n\n⏰ **{}** ({})",
+            reminders.label, reminders.due
+        ));
+        for item in &reminders.homework {
+            text.push_str(&format!("\n- 📝 {}: {}", item.subject, item.title));
+        }
+        for item in &reminders.events {
+            text.push_str(&format!("\n- 📅 {}", item.title));
+        }
+    }
+
+    let analyses = [
+        ("📢", "Ogłoszenia", &digest.announcements_analysis),
+        ("✉️", "Wiadomości", &digest.messages_analysis),
+        ("⭐", "Nowe oceny", &digest.grades_analysis),
+        ("📝", "Zadania domowe", &digest.homework_analysis),
+        ("📅", "Wydarzenia", &digest.events_analysis),
+    ];
+    for (icon, title, analysis) in analyses {
+        if let Some(analysis) = analysis {
+            text.push_str(&render_analysis(icon, title, analysis));
+        }
+    }
+
+    if !digest.grade_changes.is_empty() {
+        text.push_str("\n\n✏️ **Zmienione oceny**");
+        for change in &digest.grade_changes {
+            text.push_str(&format!(
+                "\n- {}: {}",
+                change.grade.subject,
+                change.details()
+            ));
+        }
+    }
+    if !digest.attendance.is_empty() {
+        text.push_str(&format!(
+            "\n\n🏫 **Frekwencja**: {} nowych nieobecności lub spóźnień",
+            digest.attendance.len()
+        ));
+    }
+    if !digest.timetable_changes.is_empty() {
+        text.push_str("\n\n🔄 **Zmiany w planie**");
+        for change in &digest.timetable_changes {
+            text.push_str(&format!(
+                "\n- {} lekcja {}: {} - {}",
+                change.date, change.lesson_no, change.kind, change.details
+            ));
+        }
+    }
+
+    text.push_str(&format!("\n\n[Otwórz Librusa]({})", LIBRUS_URL));
+    text
+}
+
+/// Cuts `text` to at most `max_chars` characters, marking the cut with an ellipsis.
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::models::Urgency;
+
+    struct FakeNotifier {
+        fail: bool,
+        push: bool,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Notifier for FakeNotifier {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn is_push(&self) -> bool {
+            self.push
+        }
+
+        async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+            let mut received = self.received.lock().unwrap();
+            received.extend(digests.iter().map(|digest| digest.student.clone()));
+            if self.fail {
+                Err(anyhow!("boom"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn fake(fail: bool) -> Box<dyn Notifier> {
+        Box::new(FakeNotifier {
+            fail,
+            push: false,
+            received: Arc::default(),
+        })
+    }
+
+    fn digest() -> Digest {
+        Digest {
+            student: "Emilka".to_string(),
+            homework_analysis: Some(Analysis {
+                urgency: Urgency::Normalne,
+                summary: "Czytanie na środę.".to_string(),
+                key_points: vec!["📖 **środa** - str. 12".to_string()],
+                action_items: Vec::new(),
+            }),
+            ..Digest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_channel_does_not_block_others() {
+        let digests = vec![digest()];
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let working: Box<dyn Notifier> = Box::new(FakeNotifier {
+            fail: false,
+            push: false,
+            received: received.clone(),
+        });
+        // Items stay pending when any channel failed
+        let notified = notify_all(&[fake(true), working], &digests).await;
+        assert!(notified.error.is_some());
+        assert_eq!(notified.delivered, vec![false]);
+        assert_eq!(*received.lock().unwrap(), vec!["Emilka"]);
+        let notified = notify_all(&[fake(false), fake(false)], &digests).await;
+        assert!(notified.error.is_none());
+        assert_eq!(notified.delivered, vec![true]);
+    }
+
+    /// Sends each digest alone and fails for Jaś.
+    struct PerStudentNotifier;
+
+    #[async_trait]
+    impl Notifier for PerStudentNotifier {
+        fn name(&self) -> &str {
+            "per-student"
+        }
+
+        fn sends_each_digest(&self) -> bool {
+            true
+        }
+
+        async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+            if digests.iter().any(|digest| digest.student == "Jaś") {
+                Err(anyhow!("boom"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_for_one_digest_leaves_the_others_delivered() {
+        let mut jas = digest();
+        jas.student = "Jaś".to_string();
+        let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(PerStudentNotifier)];
+        let notified = notify_all(&notifiers, &[digest(), jas, Digest::default()]).await;
+        assert!(notified.error.is_some());
+        assert_eq!(notified.delivered, vec![true, false, true]);
+    }
+
+    #[tokio::test]
+    async fn test_printed_email_does_not_count_as_delivered() {
+        let email = EmailNotifier::from_config(&crate::config::EmailConfig::default());
+        let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(email)];
+        let notified = notify_all(&notifiers, &[digest()]).await;
+        assert!(notified.error.is_some());
+        assert_eq!(notified.delivered, vec![false]);
+    }
+
+    #[tokio::test]
+    async fn test_push_channels_carry_everything_next_to_printed_email() {
+        let email = EmailNotifier::from_config(&crate::config::EmailConfig::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let push: Box<dyn Notifier> = Box::new(FakeNotifier {
+            fail: false,
+            push: true,
+            received: received.clone(),
+        });
+        let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(email), push];
+        // Not urgent, yet pushed, because the email is only printed
+        assert!(notify_all(&notifiers, &[digest()]).await.error.is_none());
+        assert_eq!(*received.lock().unwrap(), vec!["Emilka"]);
+    }
+
+    #[tokio::test]
+    async fn test_push_channels_only_get_urgent_digests_next_to_email() {
+        let mut urgent = digest();
+        urgent.student = "Jaś".to_string();
+        urgent.homework_analysis.as_mut().unwrap().urgency = Urgency::Pilne;
+        let digests = vec![digest(), urgent];
+
+        let channel = |push: bool| {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let notifier: Box<dyn Notifier> = Box::new(FakeNotifier {
+                fail: false,
+                push,
+                received: received.clone(),
+            });
+            (notifier, received)
+        };
+        let (email, email_received) = channel(false);
+        let (push, push_received) = channel(true);
+        assert!(notify_all(&[email, push], &digests).await.error.is_none());
+        assert_eq!(*email_received.lock().unwrap(), vec!["Emilka", "Jaś"]);
+        assert_eq!(*push_received.lock().unwrap(), vec!["Jaś"]);
+
+        let (push, push_received) = channel(true);
+        assert!(notify_all(&[push], &digests).await.error.is_none());
+        assert_eq!(*push_received.lock().unwrap(), vec!["Emilka", "Jaś"]);
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let text = render_markdown(&digest());
+        assert!(text.starts_with("**Emilka** [NORMALNE]"));
+        assert!(text.contains(
+            "📝 **Zadania domowe** (NORMALNE)\nCzytanie na środę.\n- 📖 **środa** - str. 12"
+        ));
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("zażółć", 10), "zażółć");
+        assert_eq!(truncate_chars("zażółć", 4), "zaż…");
+    }
+}
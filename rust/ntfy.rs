@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+
+use crate::config::NtfyConfig;
+use crate::email::max_urgency;
+use crate::models::{Digest, Urgency};
+use crate::notifier::{render_markdown, Notifier, LIBRUS_URL};
+
+pub const NTFY_SERVER: &str = "https://ntfy.sh";
+
+/// Publishes one message per student to an ntfy topic.
+pub struct NtfyNotifier {
+    http: reqwest::Client,
+    server: String,
+    topic: String,
+    token: Option<String>,
+}
+
+impl NtfyNotifier {
+    pub fn from_config(config: &NtfyConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            server: config.server.trim_end_matches('/').to_string(),
+            topic: config.topic.clone(),
+            token: config.token.clone(),
+        }
+    }
+}
+
+fn priority(urgency: Urgency) -> &'static str {
+    match urgency {
+        Urgency::Pilne => "high",
+        Urgency::Normalne => "default",
+        Urgency::Niepilne => "low",
+    }
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    fn sends_each_digest(&self) -> bool {
+        true
+    }
+
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        for digest in digests {
+            let urgency = max_urgency(&[digest]);
+            let mut builder = self
+                .http
+                .post(format!("{}/{}", self.server, self.topic))
+                .header("Title", format!("Librus: {}", digest.student))
+                .header("Priority", priority(urgency))
+                .header("Tags", "school")
+                .header("Click", LIBRUS_URL)
+                .header("Markdown", "yes")
+                .body(render_markdown(digest));
+            if let Some(token) = &self.token {
+                builder = builder.bearer_auth(token);
+            }
+
+            let resp = builder.send().await.context("ntfy request failed")?;
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(anyhow!("ntfy error: {} - {}", status, body));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server;
+    use crate::models::Analysis;
+
+    #[tokio::test]
+    async fn test_publishes_to_topic_with_priority() {
+        let (server, request) = stub_server("{}".to_string()).await;
+        let notifier = NtfyNotifier {
+            http: reqwest::Client::new(),
+            server,
+            topic: "librus-emilka".to_string(),
+            token: None,
+        };
+        let digest = Digest {
+            student: "Emilka".to_string(),
+            events_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Sprawdzian jutro.".to_string(),
+                key_points: Vec::new(),
+                action_items: Vec::new(),
+            }),
+            ..Digest::default()
+        };
+        notifier.notify(&[&digest]).await.unwrap();
+
+        let request = request.await.unwrap().to_lowercase();
+        assert!(request.starts_with("post /librus-emilka"));
+        assert!(request.contains("priority: high"));
+        assert!(request.contains("sprawdzian jutro."));
+    }
+}
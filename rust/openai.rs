@@ -1,32 +1,60 @@
-use std::env;
-
 use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 
-use chrono::Local;
-
-use crate::models::{Analysis, AnnouncementItem, GradeItem, HomeworkItem, MessageItem};
+use crate::analyzer::{parse_analysis, Analyzer};
+use crate::config::{CompatibleConfig, OpenAiConfig, RetryConfig};
+use crate::models::Analysis;
+use crate::retry::{send_with_retry, LlmError};
 
-const OPENAI_API_URL: &str = "https://api.openai.com/v1/chat/completions";
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
 
+/// Chat Completions client for OpenAI and OpenAI-compatible servers
+/// (Ollama, llama.cpp server, vLLM).
 #[derive(Clone)]
 pub struct OpenAiClient {
     http: reqwest::Client,
-    api_key: String,
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
     model: String,
+    /// Sends [`Analysis::json_schema`] instead of only asking for a JSON object.
+    json_schema: bool,
+    retry: RetryConfig,
 }
 
 impl OpenAiClient {
-    pub fn from_env() -> Result<Self> {
-        let api_key =
-            env::var("OPENAI_API_KEY").map_err(|_| anyhow!("OPENAI_API_KEY must be set"))?;
-        let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-5.2".to_string());
-        Ok(Self {
+    pub fn new(config: &OpenAiConfig, retry: &RetryConfig) -> Self {
+        Self {
             http: reqwest::Client::new(),
-            api_key,
-            model,
-        })
+            name: "OpenAI".to_string(),
+            base_url: OPENAI_BASE_URL.to_string(),
+            api_key: Some(config.api_key.clone()),
+            model: config.model.clone(),
+            json_schema: true,
+            retry: retry.clone(),
+        }
+    }
+
+    /// Any server exposing `/chat/completions`, e.g. `LLM_BASE_URL=http://localhost:11434/v1`.
+    pub fn compatible(config: &CompatibleConfig, retry: &RetryConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            name: format!("OpenAI-compatible ({})", config.model),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+            model: config.model.clone(),
+            json_schema: config.json_schema,
+            retry: retry.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Analyzer for OpenAiClient {
+    fn name(&self) -> &str {
+        &self.name
     }
 
     async fn chat_json(&self, system: &str, user: &str) -> Result<Analysis> {
@@ -42,155 +70,52 @@ impl OpenAiClient {
                     content: user.to_string(),
                 },
             ],
-            response_format: ResponseFormat {
-                r#type: "json_object".to_string(),
+            response_format: if self.json_schema {
+                ResponseFormat::JsonSchema {
+                    json_schema: JsonSchema {
+                        name: "analysis".to_string(),
+                        strict: true,
+                        schema: Analysis::json_schema(),
+                    },
+                }
+            } else {
+                ResponseFormat::JsonObject
             },
             temperature: 0.3,
         };
 
-        let resp = self
-            .http
-            .post(OPENAI_API_URL)
-            .header(CONTENT_TYPE, "application/json")
-            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .json(&request)
-            .send()
-            .await
-            .context("OpenAI request failed")?;
-
-        let status = resp.status();
-        let body = resp.text().await.context("OpenAI response read failed")?;
-
-        if !status.is_success() {
-            return Err(anyhow!("OpenAI API error: {} - {}", status, body));
-        }
+        let body = send_with_retry(&self.retry, "OpenAI", || {
+            let mut builder = self
+                .http
+                .post(format!("{}/chat/completions", self.base_url))
+                .header(CONTENT_TYPE, "application/json");
+            if let Some(api_key) = &self.api_key {
+                builder = builder.header(AUTHORIZATION, format!("Bearer {}", api_key));
+            }
+            builder.json(&request)
+        })
+        .await?;
 
         let parsed: OpenAiResponse =
             serde_json::from_str(&body).context("OpenAI response parse failed")?;
-        let content = parsed
+        let message = &parsed
             .choices
-            .get(0)
+            .first()
             .ok_or_else(|| anyhow!("OpenAI response missing choices"))?
-            .message
-            .content
-            .clone();
-
-        let analysis: Analysis =
-            serde_json::from_str(&content).context("OpenAI JSON content parse failed")?;
-        Ok(analysis)
-    }
-
-    pub async fn summarize_messages(&self, items: &[MessageItem]) -> Result<Analysis> {
-        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
-        let items_text = items
-            .iter()
-            .enumerate()
-            .map(|(idx, item)| {
-                format!(
-                    "{}. {}\n   Od: {}\n   Data: {}\n   Treść: {}",
-                    idx + 1,
-                    item.title,
-                    item.user,
-                    item.date,
-                    item.body
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n");
-
-        let prompt = format!(
-            "DZISIAJ JEST: {}\n\nPrzeanalizuj wiadomości ze szkoły dla klasy 1A SP ETE.\n\nKONTEKST: Emilka, klasa 1A SP ETE w Gliwicach, wychowawca p. Monika Podżorska.\n\nWIADOMOŚCI:\n{}\n\nIGNORUJ CAŁKOWICIE:\n- Wiadomości WYSŁANE PRZEZ RODZICA (np. \"Usprawiedliwienie\", prośby o zwolnienie) - to nasze wiadomości, nie od szkoły\n- Ogłoszenia z \"4-8 SP\", \"7/8 SP\", \"1-4 LO\", \"klasy 4-8\" - nie dotyczą klasy 1\n- Wiadomości od \"SuperAdministrator\"\n- Odpowiedzi \"Re:\" które tylko potwierdzają (np. \"Dziękuję za informację\")\n- WYDARZENIA KTÓRE JUŻ MINĘŁY (data w przeszłości względem dzisiaj)\n\nTYPY WIADOMOŚCI DO ROZPOZNANIA:\n- CZYTANIE: \"tekst do ładnego czytania\", strony z podręcznika\n- WYCIECZKA: koszty, godziny wyjazdu/powrotu, co zabrać\n- PRZYBORY: materiały na plastykę, mundurki\n- KONSULTACJE: terminy spotkań z nauczycielami\n- TESTY/SPRAWDZIANY: daty, zakres materiału\n- AKCJE: zbiórki, terminy, wpłaty\n\nPILNOŚĆ (względem dzisiejszej daty {}):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-14 dni\n- NIEPILNE: termin > 14 dni lub informacyjne\n\nWYODRĘBNIJ KONKRETNIE:\n💰 Kwoty i terminy wpłat\n🎒 Przybory do przyniesienia\n📖 Strony/materiał do nauki\n📅 Daty i godziny wydarzeń\n⏰ Godziny zbiórek\n\nFORMAT: Podsumowanie (2-3 zdania) to Twoja analiza CO TRZEBA ZROBIĆ.\nkeyPoints: max 5-7 najważniejszych, posortowane od najpilniejszych.\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co rodzic musi zrobić\", \"keyPoints\": [\"emoji **termin** - działanie\"]}}",
-            today, items_text, today
-        );
-
-        let system = "Jesteś asystentem rodzica uczennicy klasy 1A SP ETE. Wyodrębniasz KONKRETNE działania: co kupić, ile zapłacić, jakie strony przeczytać, kiedy przyjść. Ignorujesz wiadomości dla innych klas i wydarzenia które już minęły. Odpowiadasz TYLKO JSON.";
-
-        self.chat_json(system, &prompt).await
-    }
-
-    pub async fn summarize_announcements(&self, items: &[AnnouncementItem]) -> Result<Analysis> {
-        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
-        let items_text = items
-            .iter()
-            .enumerate()
-            .map(|(idx, item)| {
-                format!(
-                    "{}. {}\n   Autor: {}\n   Data: {}\n   Treść: {}",
-                    idx + 1,
-                    item.title,
-                    item.author,
-                    item.date,
-                    item.content
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n");
-
-        let prompt = format!(
-            "DZISIAJ JEST: {}\n\nPrzeanalizuj ogłoszenia ze szkoły dla klasy 1A SP ETE.\n\nKONTEKST: Emilka, klasa 1A SP ETE w Gliwicach, wychowawca p. Monika Podżorska.\n\nOGŁOSZENIA:\n{}\n\nIGNORUJ CAŁKOWICIE:\n- Ogłoszenia z \"4-8 SP\", \"7/8 SP\", \"1-4 LO\", \"klasy 4-8\" - nie dotyczą klasy 1\n- Ogłoszenia od \"SuperAdministrator\" jeśli są ogólne\n- WYDARZENIA KTÓRE JUŻ MINĘŁY\n\nPILNOŚĆ (względem dzisiejszej daty):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-14 dni\n- NIEPILNE: termin > 14 dni lub informacyjne\n\nWYODRĘBNIJ KONKRETNIE:\n💰 Kwoty i terminy wpłat\n🎒 Przybory do przyniesienia\n📅 Daty i godziny wydarzeń\n⏰ Godziny zbiórek\n\nFORMAT: Podsumowanie (2-3 zdania). keyPoints: max 5-7 najważniejszych.\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co rodzic musi zrobić\", \"keyPoints\": [\"emoji **termin** - działanie\"]}}",
-            today, items_text
-        );
-
-        let system = "Jesteś asystentem rodzica uczennicy klasy 1A SP ETE. Wyodrębniasz KONKRETNE działania. Ignorujesz ogłoszenia dla innych klas i minione wydarzenia. Odpowiadasz TYLKO JSON.";
-
-        self.chat_json(system, &prompt).await
-    }
-
-    pub async fn summarize_grades(&self, items: &[GradeItem]) -> Result<Analysis> {
-        let items_text = items
-            .iter()
-            .enumerate()
-            .map(|(idx, item)| {
-                format!(
-                    "{}. Przedmiot: {}\n   Ocena: {}\n   Informacje: {}",
-                    idx + 1,
-                    item.subject,
-                    item.value,
-                    item.info
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n");
-
-        let prompt = format!(
-            "Przeanalizuj nowe oceny Emilki z klasy 1A SP.\n\nOCENY:\n{}\n\nSYSTEM OCENIANIA:\n- T = znakomicie (najlepsza)\n- + = dobrze\n- +/- = przeciętnie\n\nKATEGORIE OCEN: aktywność, sprawdzian, odpowiedź ustna, praca na lekcji, podsumowanie miesiąca\n\nZADANIA:\n1. Wyodrębnij kluczowe uwagi nauczycieli (np. \"mniej rozmawiać z koleżanką\", wyniki procentowe sprawdzianów)\n2. Określ pilność: PILNE tylko gdy są uwagi o problemach, NORMALNE dla pozytywnych ocen\n3. Napisz podsumowanie (2-3 zdania) w pozytywnym tonie podkreślając sukcesy\n4. Wypisz oceny: 📚 przedmiot | ⭐ **ocena** | 💬 istotna uwaga nauczyciela\n\nFORMAT: Podsumowanie to Twoja analiza - NIE cytuj dosłownie komentarzy nauczycieli.\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Twoja analiza postępów\", \"keyPoints\": [\"📚 Przedmiot | ⭐ **T** | 💬 uwaga\"]}}",
-            items_text
-        );
-
-        let system = "Jesteś asystentem rodzica uczennicy 1 klasy SP. Analizujesz oceny i wyciągasz konstruktywne uwagi nauczycieli. Odpowiadasz TYLKO JSON. Podsumowanie to Twoja analiza, nie cytat.";
-
-        self.chat_json(system, &prompt).await
-    }
+            .message;
+        // Under a strict schema a refusal comes without content; asking again won't help
+        let Some(content) = &message.content else {
+            return Err(LlmError::Permanent {
+                provider: "OpenAI".to_string(),
+                message: format!(
+                    "model refused: {}",
+                    message.refusal.as_deref().unwrap_or("no content")
+                ),
+            }
+            .into());
+        };
 
-    pub async fn summarize_homework(&self, items: &[HomeworkItem]) -> Result<Analysis> {
-        let today = Local::now().format("%Y-%m-%d (%A)").to_string();
-        let items_text = items
-            .iter()
-            .enumerate()
-            .map(|(idx, item)| {
-                format!(
-                    "{}. {}\n   Przedmiot: {}\n   Nauczyciel: {}\n   Typ: {}\n   Od: {}\n   Do: {}\n   Treść: {}",
-                    idx + 1,
-                    item.title,
-                    item.subject,
-                    item.user,
-                    item.kind,
-                    item.from,
-                    item.to,
-                    item.content
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n");
-
-        let prompt = format!(
-            "DZISIAJ JEST: {}\n\nPrzeanalizuj zadania domowe dla klasy 1A SP ETE.\n\nZADANIA DOMOWE:\n{}\n\nIGNORUJ zadania z terminem który już minął.\n\nPILNOŚĆ (względem dzisiejszej daty):\n- PILNE: termin w ciągu 3 dni\n- NORMALNE: termin 4-7 dni\n- NIEPILNE: termin > 7 dni\n\nFORMAT: Podsumowanie (2-3 zdania). keyPoints: posortowane od najpilniejszych.\n\nJSON:\n{{\"urgency\": \"PILNE|NORMALNE|NIEPILNE\", \"summary\": \"Co trzeba zrobić\", \"keyPoints\": [\"📝 **przedmiot** - zadanie | ⏰ termin\"]}}",
-            today, items_text
-        );
-
-        let system = "Jesteś asystentem rodzica uczennicy klasy 1A SP. Analizujesz zadania domowe, ignorujesz minione terminy. Odpowiadasz TYLKO JSON.";
-
-        self.chat_json(system, &prompt).await
+        parse_analysis(content)
     }
 }
 
@@ -209,8 +134,20 @@ struct ChatMessage {
 }
 
 #[derive(Debug, Serialize)]
-struct ResponseFormat {
-    r#type: String,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseFormat {
+    JsonObject,
+    /// Structured outputs: the reply is constrained to the schema.
+    JsonSchema {
+        json_schema: JsonSchema,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSchema {
+    name: String,
+    strict: bool,
+    schema: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -225,5 +162,69 @@ struct Choice {
 
 #[derive(Debug, Deserialize)]
 struct ChoiceMessage {
-    content: String,
+    content: Option<String>,
+    refusal: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server;
+    use crate::models::Urgency;
+
+    #[tokio::test]
+    async fn test_compatible_backend_against_stub_server() {
+        let content = r#"{"urgency": "NORMALNE", "summary": "Zebranie", "keyPoints": ["📅 **12.03** - zebranie"]}"#;
+        let body = serde_json::json!({
+            "choices": [{"message": {"content": content}}]
+        })
+        .to_string();
+        let (base_url, request) = stub_server(body).await;
+
+        let client = OpenAiClient {
+            http: reqwest::Client::new(),
+            name: "stub".to_string(),
+            base_url,
+            api_key: None,
+            model: "llama3".to_string(),
+            json_schema: true,
+            retry: RetryConfig::default(),
+        };
+        let analysis = client.chat_json("system", "user").await.unwrap();
+        assert_eq!(analysis.urgency, Urgency::Normalne);
+        assert_eq!(analysis.key_points.len(), 1);
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /chat/completions"));
+        assert!(request.contains("\"model\":\"llama3\""));
+        assert!(request.contains("\"type\":\"json_schema\""));
+        assert!(request.contains("\"strict\":true"));
+        assert!(!request.to_lowercase().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn test_refusal_is_a_permanent_error() {
+        let body = serde_json::json!({
+            "choices": [{"message": {"content": null, "refusal": "I can't help with that."}}]
+        })
+        .to_string();
+        let (base_url, _request) = stub_server(body).await;
+
+        let client = OpenAiClient {
+            http: reqwest::Client::new(),
+            name: "stub".to_string(),
+            base_url,
+            api_key: None,
+            model: "gpt".to_string(),
+            json_schema: true,
+            retry: RetryConfig::default(),
+        };
+        let err = client.chat_json("system", "user").await.unwrap_err();
+        match err.downcast_ref::<LlmError>() {
+            Some(LlmError::Permanent { message, .. }) => {
+                assert_eq!(message, "model refused: I can't help with that.")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
 }
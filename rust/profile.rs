@@ -0,0 +1,236 @@
+use serde::Deserialize;
+
+const DEFAULT_GRADING_SCALE: &[&str] = &[
+    "T = znakomicie (najlepsza)",
+    "+ = dobrze",
+    "+/- = przeciętnie",
+];
+
+/// Used when the grading scale is set to just `1-6`.
+const NUMERIC_GRADING_SCALE: &[&str] = &[
+    "6 = celujący (najlepsza)",
+    "5 = bardzo dobry",
+    "4 = dobry",
+    "3 = dostateczny",
+    "2 = dopuszczający",
+    "1 = niedostateczny",
+];
+
+/// Describes the child the prompts are written for.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StudentProfile {
+    pub name: String,
+    pub grade_level: Option<u8>,
+    #[serde(rename = "class")]
+    pub class_name: String,
+    pub school: String,
+    pub city: String,
+    pub teacher: String,
+    pub ignore_patterns: Vec<String>,
+    pub grading_scale: Vec<String>,
+}
+
+impl StudentProfile {
+    /// Reads `<prefix>STUDENT_*` variables, e.g. `STUDENT_NAME` or `LIBRUS_JAS_STUDENT_NAME`.
+    ///
+    /// `STUDENT_IGNORE_PATTERNS` is comma separated, `STUDENT_GRADING_SCALE` is
+    /// semicolon separated (`T = znakomicie;+ = dobrze`). Missing values stay empty
+    /// until [`StudentProfile::fill_defaults`].
+    pub fn from_env(prefix: &str, env: &dyn Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| {
+            env(&format!("{}STUDENT_{}", prefix, name))
+                .map(|val| val.trim().to_string())
+                .unwrap_or_default()
+        };
+        let list = |name: &str, separator: char| {
+            var(name)
+                .split(separator)
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            name: var("NAME"),
+            grade_level: var("GRADE_LEVEL").parse().ok(),
+            class_name: var("CLASS"),
+            school: var("SCHOOL"),
+            city: var("CITY"),
+            teacher: var("TEACHER"),
+            ignore_patterns: list("IGNORE_PATTERNS", ','),
+            grading_scale: list("GRADING_SCALE", ';'),
+        }
+    }
+
+    /// Uses `fallback_name` for a missing name and the descriptive T/+/+- scale for a
+    /// missing grading scale; a scale of just `1-6` expands to the numeric grades.
+    pub fn fill_defaults(&mut self, fallback_name: &str) {
+        if self.name.is_empty() {
+            self.name = fallback_name.to_string();
+        }
+        let preset = match self.grading_scale.as_slice() {
+            [] => Some(DEFAULT_GRADING_SCALE),
+            [scale] if scale == "1-6" => Some(NUMERIC_GRADING_SCALE),
+            _ => None,
+        };
+        if let Some(preset) = preset {
+            self.grading_scale = preset.iter().map(|grade| grade.to_string()).collect();
+        }
+    }
+
+    /// Class and school, e.g. "1A SP ETE".
+    pub fn class_label(&self) -> String {
+        [self.class_name.as_str(), self.school.as_str()]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Genitive description used in prompts, e.g. "klasy 1A SP ETE".
+    pub fn class_description(&self) -> String {
+        let class_label = self.class_label();
+        if class_label.is_empty() {
+            "dziecka".to_string()
+        } else {
+            format!("klasy {}", class_label)
+        }
+    }
+
+    /// Header line shown in notifications, e.g. "Emilka • Klasa 1A SP ETE".
+    pub fn label(&self) -> String {
+        let class_label = self.class_label();
+        if class_label.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} • Klasa {}", self.name, class_label)
+        }
+    }
+
+    /// Who the parent's assistant works for, used in system prompts.
+    pub fn audience(&self) -> String {
+        let class_label = self.class_label();
+        if class_label.is_empty() {
+            "dziecka".to_string()
+        } else {
+            format!("dziecka z klasy {}", class_label)
+        }
+    }
+
+    /// One-line context paragraph for user prompts.
+    pub fn context(&self) -> String {
+        let mut context = self.name.clone();
+        let class_label = self.class_label();
+        if !class_label.is_empty() {
+            context.push_str(&format!(", klasa {}", class_label));
+        }
+        if !self.city.is_empty() {
+            context.push_str(&format!(" w mieście {}", self.city));
+        }
+        if !self.teacher.is_empty() {
+            context.push_str(&format!(", wychowawca p. {}", self.teacher));
+        }
+        context.push('.');
+        context
+    }
+
+    /// Bullet line telling the model which other-class markers to skip, if any.
+    pub fn ignore_rule(&self) -> String {
+        if self.ignore_patterns.is_empty() {
+            return String::new();
+        }
+        let patterns = self
+            .ignore_patterns
+            .iter()
+            .map(|pattern| format!("\"{}\"", pattern))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.grade_level {
+            Some(level) => format!(
+                "- Ogłoszenia z {} - nie dotyczą klasy {}\n",
+                patterns, level
+            ),
+            None => format!("- Ogłoszenia z {} - nie dotyczą tej klasy\n", patterns),
+        }
+    }
+
+    pub fn grading_scale_text(&self) -> String {
+        self.grading_scale
+            .iter()
+            .map(|grade| format!("- {}", grade))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> StudentProfile {
+        StudentProfile {
+            name: "Emilka".to_string(),
+            grade_level: Some(1),
+            class_name: "1A".to_string(),
+            school: "SP ETE".to_string(),
+            city: "Gliwice".to_string(),
+            teacher: "Monika Podżorska".to_string(),
+            ignore_patterns: vec!["4-8 SP".to_string(), "1-4 LO".to_string()],
+            grading_scale: vec!["T = znakomicie".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_label_and_context() {
+        let profile = profile();
+        assert_eq!(profile.label(), "Emilka • Klasa 1A SP ETE");
+        assert_eq!(
+            profile.context(),
+            "Emilka, klasa 1A SP ETE w mieście Gliwice, wychowawca p. Monika Podżorska."
+        );
+    }
+
+    #[test]
+    fn test_ignore_rule() {
+        let mut profile = profile();
+        assert_eq!(
+            profile.ignore_rule(),
+            "- Ogłoszenia z \"4-8 SP\", \"1-4 LO\" - nie dotyczą klasy 1\n"
+        );
+        profile.ignore_patterns.clear();
+        assert_eq!(profile.ignore_rule(), "");
+    }
+
+    #[test]
+    fn test_minimal_profile() {
+        let profile = StudentProfile {
+            name: "Jaś".to_string(),
+            grade_level: None,
+            class_name: String::new(),
+            school: String::new(),
+            city: String::new(),
+            teacher: String::new(),
+            ignore_patterns: Vec::new(),
+            grading_scale: Vec::new(),
+        };
+        assert_eq!(profile.label(), "Jaś");
+        assert_eq!(profile.audience(), "dziecka");
+        assert_eq!(profile.context(), "Jaś.");
+    }
+
+    #[test]
+    fn test_grading_scale_defaults() {
+        let mut profile = profile();
+        profile.grading_scale.clear();
+        profile.fill_defaults("emilka");
+        assert_eq!(profile.grading_scale[0], "T = znakomicie (najlepsza)");
+
+        profile.grading_scale = vec!["1-6".to_string()];
+        profile.fill_defaults("emilka");
+        assert_eq!(profile.grading_scale.len(), 6);
+        assert_eq!(profile.grading_scale[0], "6 = celujący (najlepsza)");
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::config::RetryConfig;
+
+/// Failure of an LLM API call, split by whether trying again later can help.
+///
+/// Backends return it inside `anyhow::Error`; use [`LlmError::is_transient_error`] to
+/// classify an error coming out of an [`Analyzer`](crate::analyzer::Analyzer).
+#[derive(Debug)]
+pub enum LlmError {
+    /// Rate limiting, server errors, timeouts and connection problems, still failing
+    /// after every retry.
+    Transient { provider: String, message: String },
+    /// Errors a retry cannot fix, such as a bad API key or an invalid request.
+    Permanent { provider: String, message: String },
+}
+
+impl LlmError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, LlmError::Transient { .. })
+    }
+
+    pub fn is_transient_error(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<LlmError>()
+            .is_some_and(LlmError::is_transient)
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Transient { provider, message } => {
+                write!(f, "{} API temporarily unavailable: {}", provider, message)
+            }
+            LlmError::Permanent { provider, message } => {
+                write!(f, "{} API error: {}", provider, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+/// Sends the request built by `request`, retrying rate limits (429), server errors
+/// (5xx), timeouts and connection failures with jittered exponential backoff.
+///
+/// A `Retry-After` header, in seconds or as an HTTP date, replaces the computed delay,
+/// but never beyond `max_backoff_ms`.
+/// Returns the body of the first successful response.
+pub async fn send_with_retry(
+    config: &RetryConfig,
+    provider: &str,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<String, LlmError> {
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let mut attempt = 0;
+    loop {
+        let (message, retry_after) = match request().timeout(timeout).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                return resp.text().await.map_err(|err| LlmError::Transient {
+                    provider: provider.to_string(),
+                    message: format!("response read failed: {}", err),
+                });
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                let retry_after = retry_after(&resp);
+                let body = resp.text().await.unwrap_or_default();
+                let message = format!("{} - {}", status, body);
+                if !is_retryable(status) {
+                    return Err(LlmError::Permanent {
+                        provider: provider.to_string(),
+                        message,
+                    });
+                }
+                (message, retry_after)
+            }
+            Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+                (format!("request failed: {}", err), None)
+            }
+            Err(err) => {
+                return Err(LlmError::Permanent {
+                    provider: provider.to_string(),
+                    message: format!("request failed: {}", err),
+                })
+            }
+        };
+
+        if attempt >= config.max_retries {
+            return Err(LlmError::Transient {
+                provider: provider.to_string(),
+                message: format!("{} (after {} retries)", message, attempt),
+            });
+        }
+        let delay = retry_delay(config, retry_after, attempt);
+        attempt += 1;
+        tracing::warn!(
+            provider,
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            error = %message,
+            "LLM request failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, Utc::now())
+}
+
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+/// The delay the server asked for, or the computed backoff, capped at `max_backoff_ms`
+/// so a server cannot stall the run for hours.
+fn retry_delay(config: &RetryConfig, retry_after: Option<Duration>, attempt: u32) -> Duration {
+    match retry_after {
+        Some(delay) => delay.min(Duration::from_millis(config.max_backoff_ms)),
+        None => backoff(config, attempt),
+    }
+}
+
+/// Exponential backoff capped at `max_backoff_ms`, randomised to between half and the
+/// full delay so parallel clients do not retry in lockstep.
+fn backoff(config: &RetryConfig, attempt: u32) -> Duration {
+    let exponential = config
+        .initial_backoff_ms
+        .saturating_mul(1u64 << attempt.min(32))
+        .min(config.max_backoff_ms);
+    let half = exponential / 2;
+    Duration::from_millis(half + jitter(exponential - half))
+}
+
+/// A pseudo-random number in `0..=max`; good enough to spread out retries.
+fn jitter(max: u64) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos() as u64)
+        .unwrap_or_default();
+    nanos % (max + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server_sequence;
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            timeout_secs: 5,
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2025-03-10T08:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_retry_after("7", now), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Mon, 10 Mar 2025 08:00:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retry_after_is_capped() {
+        let config = RetryConfig {
+            max_backoff_ms: 30_000,
+            ..config()
+        };
+        assert_eq!(
+            retry_delay(&config, Some(Duration::from_secs(7)), 0),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            retry_delay(&config, Some(Duration::from_secs(86_400)), 0),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_backoff_is_capped_and_jittered() {
+        let config = RetryConfig {
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+            ..config()
+        };
+        for attempt in 0..40 {
+            let delay = backoff(&config, attempt).as_millis() as u64;
+            let full = (1000u64 << attempt.min(5)).min(30_000);
+            assert!(delay >= full / 2 && delay <= full, "{} {}", attempt, delay);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors_then_succeeds() {
+        let (base_url, requests) = stub_server_sequence(vec![
+            (429, "{}".to_string()),
+            (503, "{}".to_string()),
+            (200, "ok".to_string()),
+        ])
+        .await;
+        let http = reqwest::Client::new();
+        let body = send_with_retry(&config(), "Stub", || http.post(&base_url))
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+        assert_eq!(requests.await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_and_exhausted_errors_are_distinct() {
+        let (base_url, _) = stub_server_sequence(vec![(401, "bad key".to_string())]).await;
+        let http = reqwest::Client::new();
+        let err = send_with_retry(&config(), "Stub", || http.post(&base_url))
+            .await
+            .unwrap_err();
+        assert!(!err.is_transient());
+
+        let (base_url, requests) = stub_server_sequence(vec![(500, "{}".to_string()); 3]).await;
+        let err = send_with_retry(&config(), "Stub", || http.post(&base_url))
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+        assert!(LlmError::is_transient_error(&anyhow::Error::new(err)));
+        assert_eq!(requests.await.unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,476 @@
+use serde::Deserialize;
+
+use crate::config::{NotifyConfig, RouteRule};
+use crate::email::section_urgency;
+use crate::models::{Analysis, Digest};
+use crate::notifier::{self, Notifier};
+
+/// Part of a digest that routing rules match on, as named in `sections`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionKind {
+    Announcements,
+    Messages,
+    Grades,
+    Homework,
+    Events,
+    Attendance,
+    Timetable,
+    Reminders,
+}
+
+impl SectionKind {
+    pub const ALL: [SectionKind; 8] = [
+        SectionKind::Announcements,
+        SectionKind::Messages,
+        SectionKind::Grades,
+        SectionKind::Homework,
+        SectionKind::Events,
+        SectionKind::Attendance,
+        SectionKind::Timetable,
+        SectionKind::Reminders,
+    ];
+
+    /// True when the digest has neither items nor an analysis for the section.
+    pub fn is_empty(self, digest: &Digest) -> bool {
+        match self {
+            SectionKind::Announcements => {
+                digest.announcements.is_empty() && digest.announcements_analysis.is_none()
+            }
+            SectionKind::Messages => {
+                digest.messages.is_empty() && digest.messages_analysis.is_none()
+            }
+            SectionKind::Grades => {
+                digest.grades.is_empty()
+                    && digest.grade_changes.is_empty()
+                    && digest.grades_analysis.is_none()
+            }
+            SectionKind::Homework => {
+                digest.homework.is_empty() && digest.homework_analysis.is_none()
+            }
+            SectionKind::Events => digest.events.is_empty() && digest.events_analysis.is_none(),
+            SectionKind::Attendance => digest.attendance.is_empty(),
+            SectionKind::Timetable => digest.timetable_changes.is_empty(),
+            SectionKind::Reminders => digest.reminders.is_empty(),
+        }
+    }
+
+    /// Lowercased analysis and item text the rule keywords are searched in.
+    fn text(self, digest: &Digest) -> String {
+        let analysis = |analysis: &Option<Analysis>| {
+            analysis
+                .iter()
+                .flat_map(|analysis| std::iter::once(&analysis.summary).chain(&analysis.key_points))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let parts: Vec<String> = match self {
+            SectionKind::Announcements => analysis(&digest.announcements_analysis)
+                .into_iter()
+                .chain(
+                    digest
+                        .announcements
+                        .iter()
+                        .map(|item| format!("{} {}", item.title, item.content)),
+                )
+                .collect(),
+            SectionKind::Messages => analysis(&digest.messages_analysis)
+                .into_iter()
+                .chain(
+                    digest
+                        .messages
+                        .iter()
+                        .map(|item| format!("{} {}", item.title, item.body)),
+                )
+                .collect(),
+            SectionKind::Grades => analysis(&digest.grades_analysis)
+                .into_iter()
+                .chain(
+                    digest
+                        .grades
+                        .iter()
+                        .chain(digest.grade_changes.iter().map(|change| &change.grade))
+                        .map(|item| format!("{} {} {}", item.subject, item.value, item.info)),
+                )
+                .collect(),
+            SectionKind::Homework => analysis(&digest.homework_analysis)
+                .into_iter()
+                .chain(
+                    digest
+                        .homework
+                        .iter()
+                        .map(|item| format!("{} {} {}", item.subject, item.title, item.content)),
+                )
+                .collect(),
+            SectionKind::Events => analysis(&digest.events_analysis)
+                .into_iter()
+                .chain(
+                    digest
+                        .events
+                        .iter()
+                        .map(|item| format!("{} {}", item.title, item.description)),
+                )
+                .collect(),
+            SectionKind::Attendance => digest
+                .attendance
+                .iter()
+                .map(|item| format!("{} {}", item.kind, item.subject))
+                .collect(),
+            SectionKind::Timetable => digest
+                .timetable_changes
+                .iter()
+                .map(|change| format!("{} {} {}", change.kind, change.subject, change.details))
+                .collect(),
+            SectionKind::Reminders => {
+                let reminders = &digest.reminders;
+                reminders
+                    .homework
+                    .iter()
+                    .map(|item| format!("{} {}", item.subject, item.title))
+                    .chain(reminders.events.iter().map(|item| item.title.clone()))
+                    .collect()
+            }
+        };
+        parts.join("\n").to_lowercase()
+    }
+}
+
+impl RouteRule {
+    /// True when the section has items and passes every condition of the rule.
+    pub fn matches(&self, digest: &Digest, section: SectionKind) -> bool {
+        if section.is_empty(digest) {
+            return false;
+        }
+        if !self.sections.is_empty() && !self.sections.contains(&section) {
+            return false;
+        }
+        if let Some(min_urgency) = self.min_urgency {
+            if section_urgency(digest, section).is_none_or(|urgency| urgency < min_urgency) {
+                return false;
+            }
+        }
+        if !self.keywords.is_empty() {
+            let text = section.text(digest);
+            if !self
+                .keywords
+                .iter()
+                .any(|keyword| text.contains(&keyword.to_lowercase()))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A copy of the digest keeping only the selected sections.
+pub fn retain_sections(digest: &Digest, keep: impl Fn(SectionKind) -> bool) -> Digest {
+    let mut copy = Digest {
+        account: digest.account.clone(),
+        student: digest.student.clone(),
+        ..Digest::default()
+    };
+    for section in SectionKind::ALL {
+        if !keep(section) {
+            continue;
+        }
+        match section {
+            SectionKind::Announcements => {
+                copy.announcements_analysis = digest.announcements_analysis.clone();
+                copy.announcements = digest.announcements.clone();
+            }
+            SectionKind::Messages => {
+                copy.messages_analysis = digest.messages_analysis.clone();
+                copy.messages = digest.messages.clone();
+            }
+            SectionKind::Grades => {
+                copy.grades_analysis = digest.grades_analysis.clone();
+                copy.grades = digest.grades.clone();
+                copy.grade_changes = digest.grade_changes.clone();
+            }
+            SectionKind::Homework => {
+                copy.homework_analysis = digest.homework_analysis.clone();
+                copy.homework = digest.homework.clone();
+            }
+            SectionKind::Events => {
+                copy.events_analysis = digest.events_analysis.clone();
+                copy.events = digest.events.clone();
+            }
+            SectionKind::Attendance => copy.attendance = digest.attendance.clone(),
+            SectionKind::Timetable => {
+                copy.timetable_changes = digest.timetable_changes.clone();
+                copy.timetable_snapshot = digest.timetable_snapshot.clone();
+            }
+            SectionKind::Reminders => copy.reminders = digest.reminders.clone(),
+        }
+    }
+    copy
+}
+
+/// The channels one rule delivers to; the default route has no rule.
+struct Route {
+    rule: Option<RouteRule>,
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+/// Splits digests between `notify.routes` (`[[notify.routes]]`) before delivery.
+///
+/// A section matched by one or more rules goes only where those rules send it;
+/// everything else goes to all channels and recipients, as without any rules.
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn from_config(config: &NotifyConfig) -> Self {
+        let mut routes = vec![Route {
+            rule: None,
+            notifiers: notifier::notifiers_from_config(config),
+        }];
+        for rule in &config.routes {
+            let mut route_config = config.clone();
+            if !rule.channels.is_empty() {
+                route_config.channels = rule.channels.clone();
+            }
+            if !rule.email_to.is_empty() {
+                route_config.email.to = rule.email_to.clone();
+            }
+            routes.push(Route {
+                rule: Some(rule.clone()),
+                notifiers: notifier::notifiers_from_config(&route_config),
+            });
+        }
+        Self { routes }
+    }
+
+    /// Channel names per route, for the startup log.
+    pub fn describe(&self) -> Vec<String> {
+        self.routes
+            .iter()
+            .map(|route| {
+                let name = route.rule.as_ref().map_or("default", |rule| &rule.name);
+                let channels: Vec<&str> = route.notifiers.iter().map(|n| n.name()).collect();
+                format!("{}: {}", name, channels.join(","))
+            })
+            .collect()
+    }
+
+    /// The digests each route receives, in route order.
+    fn split(&self, digests: &[Digest]) -> Vec<Vec<Digest>> {
+        let rules: Vec<&RouteRule> = self
+            .routes
+            .iter()
+            .filter_map(|route| route.rule.as_ref())
+            .collect();
+        self.routes
+            .iter()
+            .map(|route| {
+                digests
+                    .iter()
+                    .map(|digest| {
+                        retain_sections(digest, |section| match &route.rule {
+                            Some(rule) => rule.matches(digest, section),
+                            None => !rules.iter().any(|rule| rule.matches(digest, section)),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Delivers every route, also after one of them failed.
+    ///
+    /// A section counts as delivered only when every route carrying it delivered its
+    /// digest, so the caller marks just those and the rest is reported again by the
+    /// next run.
+    pub async fn dispatch(&self, digests: &[Digest]) -> Dispatched {
+        let splits = self.split(digests);
+        let mut route_delivered = Vec::new();
+        let mut error = None;
+        for (route, digests) in self.routes.iter().zip(&splits) {
+            if digests.iter().all(Digest::is_empty) {
+                route_delivered.push(vec![true; digests.len()]);
+                continue;
+            }
+            let notified = notifier::notify_all(&route.notifiers, digests).await;
+            if let Some(err) = notified.error {
+                tracing::error!(
+                    route = route.rule.as_ref().map_or("default", |rule| &rule.name),
+                    error = %err,
+                    "Notification route failed"
+                );
+                error.get_or_insert(err);
+            }
+            route_delivered.push(notified.delivered);
+        }
+        let delivered = digests
+            .iter()
+            .enumerate()
+            .map(|(idx, digest)| {
+                retain_sections(digest, |section| {
+                    splits
+                        .iter()
+                        .zip(&route_delivered)
+                        .all(|(split, delivered)| delivered[idx] || section.is_empty(&split[idx]))
+                })
+            })
+            .collect();
+        Dispatched { delivered, error }
+    }
+}
+
+/// Outcome of [`Router::dispatch`].
+pub struct Dispatched {
+    /// The delivered sections of each digest, in input order.
+    pub delivered: Vec<Digest>,
+    /// The first route failure.
+    pub error: Option<anyhow::Error>,
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::config::Channel;
+    use crate::models::{GradeItem, HomeworkItem, Urgency};
+
+    fn analysis(urgency: Urgency, summary: &str) -> Option<Analysis> {
+        Some(Analysis {
+            urgency,
+            summary: summary.to_string(),
+            key_points: vec!["📝 punkt".to_string()],
+            action_items: Vec::new(),
+        })
+    }
+
+    fn digest() -> Digest {
+        Digest {
+            student: "Emilka".to_string(),
+            grades_analysis: analysis(Urgency::Normalne, "Dobre oceny"),
+            grades: vec![GradeItem {
+                id: "1".to_string(),
+                subject: "Matematyka".to_string(),
+                value: "5".to_string(),
+                info: String::new(),
+                content_hash: String::new(),
+            }],
+            homework_analysis: analysis(Urgency::Pilne, "Wycieczka do muzeum"),
+            homework: vec![HomeworkItem {
+                id: "2".to_string(),
+                subject: "Polski".to_string(),
+                title: "Czytanka".to_string(),
+                kind: "Zadanie domowe".to_string(),
+                from: "2025-03-10".to_string(),
+                to: "2025-03-14".to_string(),
+                content: "Przeczytać czytankę.".to_string(),
+                user: "Anna Nowak".to_string(),
+            }],
+            ..Digest::default()
+        }
+    }
+
+    fn rule(name: &str) -> RouteRule {
+        RouteRule {
+            name: name.to_string(),
+            ..RouteRule::default()
+        }
+    }
+
+    #[test]
+    fn test_rule_matches_section_urgency_and_keywords() {
+        let digest = digest();
+        let grades = RouteRule {
+            sections: vec![SectionKind::Grades],
+            ..rule("grades")
+        };
+        assert!(grades.matches(&digest, SectionKind::Grades));
+        assert!(!grades.matches(&digest, SectionKind::Homework));
+        assert!(!rule("any").matches(&digest, SectionKind::Messages));
+
+        let urgent = RouteRule {
+            min_urgency: Some(Urgency::Pilne),
+            ..rule("urgent")
+        };
+        assert!(urgent.matches(&digest, SectionKind::Homework));
+        assert!(!urgent.matches(&digest, SectionKind::Grades));
+
+        let keyword = RouteRule {
+            keywords: vec!["MUZEUM".to_string()],
+            ..rule("keyword")
+        };
+        assert!(keyword.matches(&digest, SectionKind::Homework));
+        assert!(!keyword.matches(&digest, SectionKind::Grades));
+    }
+
+    #[test]
+    fn test_matched_sections_leave_the_default_route() {
+        let config = NotifyConfig {
+            channels: vec![Channel::Email],
+            routes: vec![RouteRule {
+                sections: vec![SectionKind::Grades],
+                email_to: vec!["mama@example.com".to_string()],
+                ..rule("grades")
+            }],
+            ..NotifyConfig::default()
+        };
+        let router = Router::from_config(&config);
+        assert_eq!(router.describe(), vec!["default: email", "grades: email"]);
+
+        let split = router.split(&[digest()]);
+        let (default, grades) = (&split[0][0], &split[1][0]);
+        assert!(default.grades.is_empty() && default.grades_analysis.is_none());
+        assert_eq!(default.homework.len(), 1);
+        assert_eq!(default.student, "Emilka");
+        assert_eq!(grades.grades.len(), 1);
+        assert!(grades.homework.is_empty() && grades.homework_analysis.is_none());
+    }
+
+    struct FakeNotifier {
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Notifier for FakeNotifier {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn notify(&self, _digests: &[&Digest]) -> Result<()> {
+            if self.fail {
+                Err(anyhow!("boom"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_reports_only_delivered_sections() {
+        let route = |rule: Option<RouteRule>, fail: bool| Route {
+            rule,
+            notifiers: vec![Box::new(FakeNotifier { fail })],
+        };
+        let grades = RouteRule {
+            sections: vec![SectionKind::Grades],
+            ..rule("grades")
+        };
+        let router = Router {
+            routes: vec![route(None, false), route(Some(grades), true)],
+        };
+
+        let dispatched = router.dispatch(&[digest()]).await;
+        assert!(dispatched.error.is_some());
+        let delivered = &dispatched.delivered[0];
+        assert_eq!(delivered.homework.len(), 1);
+        assert!(delivered.grades.is_empty() && delivered.grades_analysis.is_none());
+
+        let router = Router {
+            routes: vec![route(None, false)],
+        };
+        let dispatched = router.dispatch(&[digest()]).await;
+        assert!(dispatched.error.is_none());
+        assert_eq!(dispatched.delivered[0].grades.len(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+// Long enough to reach the next 29 February
+const MAX_SEARCH_DAYS: i64 = 366 * 4 + 1;
+
+/// Five-field cron expression (`minute hour day-of-month month day-of-week`) evaluated in
+/// local time.
+///
+/// Fields accept `*`, numbers, ranges (`1-5`), lists (`7,15`) and steps (`*/30`, `8-18/2`).
+/// Day of week counts from 0 = Sunday; 7 is also Sunday. As in cron, when both day fields
+/// are restricted a day matching either of them fires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!(
+                "Schedule '{}' must have 5 fields: minute hour day month weekday",
+                expr
+            );
+        };
+        let parse = |field: &str, name: &str, min: u32, max: u32| {
+            parse_field(field, min, max)
+                .with_context(|| format!("Invalid {} field in schedule '{}'", name, expr))
+        };
+
+        let mut weekdays = parse(weekday, "weekday", 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse(minute, "minute", 0, 59)?,
+            hours: parse(hour, "hour", 0, 23)?,
+            days: parse(day, "day", 1, 31)?,
+            months: parse(month, "month", 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let number = |value: &str| -> Result<u32> {
+        let value: u32 = value
+            .parse()
+            .map_err(|_| anyhow!("'{}' is not a number", value))?;
+        if value < min || value > max {
+            bail!("{} is outside {}-{}", value, min, max);
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(parse_step(step)?)),
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (number(start)?, number(end)?)
+        } else {
+            let start = number(range)?;
+            // `5/15` means every 15 starting at 5
+            (start, if step.is_some() { max } else { start })
+        };
+        if start > end {
+            bail!("Range {} is reversed", range);
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_step(step: &str) -> Result<usize> {
+    match step.parse() {
+        Ok(step) if step > 0 => Ok(step),
+        _ => bail!("'{}' is not a valid step", step),
+    }
+}
+
+impl Schedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// First matching minute strictly after `after`.
+    fn next_naive(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        (0..MAX_SEARCH_DAYS)
+            .map(|offset| start.date() + Duration::days(offset))
+            .filter(|date| self.matches_day(*date))
+            .flat_map(|date| {
+                (0..24u32)
+                    .filter(|hour| self.hours & (1 << hour) != 0)
+                    .flat_map(move |hour| {
+                        (0..60u32)
+                            .filter(|minute| self.minutes & (1 << minute) != 0)
+                            .filter_map(move |minute| date.and_hms_opt(hour, minute, 0))
+                    })
+            })
+            .find(|time| *time >= start)
+    }
+
+    /// Next run after `after`, skipping local times that do not exist because of a DST change.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut candidate = self.next_naive(after.naive_local())?;
+        loop {
+            if let Some(time) = Local.from_local_datetime(&candidate).earliest() {
+                return Some(time);
+            }
+            candidate = self.next_naive(candidate)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_twice_a_day_schedule() {
+        let schedule: Schedule = "0 7,15 * * *".parse().unwrap();
+        assert_eq!(
+            schedule.next_naive(at("2025-03-10 06:59")),
+            Some(at("2025-03-10 07:00"))
+        );
+        assert_eq!(
+            schedule.next_naive(at("2025-03-10 07:00")),
+            Some(at("2025-03-10 15:00"))
+        );
+        assert_eq!(
+            schedule.next_naive(at("2025-03-10 15:30")),
+            Some(at("2025-03-11 07:00"))
+        );
+    }
+
+    #[test]
+    fn test_ranges_steps_and_weekdays() {
+        // 2025-03-14 is a Friday
+        let schedule: Schedule = "*/20 8-16/4 * * 1-5".parse().unwrap();
+        assert_eq!(
+            schedule.next_naive(at("2025-03-14 16:40")),
+            Some(at("2025-03-17 08:00"))
+        );
+        assert_eq!(
+            schedule.next_naive(at("2025-03-17 08:45")),
+            Some(at("2025-03-17 12:00"))
+        );
+
+        let sunday: Schedule = "30 18 * * 7".parse().unwrap();
+        assert_eq!(
+            sunday.next_naive(at("2025-03-14 12:00")),
+            Some(at("2025-03-16 18:30"))
+        );
+
+        // Day of month or weekday, as in cron
+        let either: Schedule = "0 9 1 * 1".parse().unwrap();
+        assert_eq!(
+            either.next_naive(at("2025-03-25 10:00")),
+            Some(at("2025-03-31 09:00"))
+        );
+        assert_eq!(
+            either.next_naive(at("2025-03-31 10:00")),
+            Some(at("2025-04-01 09:00"))
+        );
+    }
+
+    #[test]
+    fn test_invalid_schedules_are_rejected() {
+        for expr in [
+            "0 7 * *",
+            "60 * * * *",
+            "0 7-5 * * *",
+            "*/0 * * * *",
+            "0 x * * *",
+        ] {
+            assert!(expr.parse::<Schedule>().is_err(), "{} should fail", expr);
+        }
+        let leap_day: Schedule = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(
+            leap_day.next_naive(at("2025-03-01 00:00")),
+            Some(at("2028-02-29 00:00"))
+        );
+    }
+}
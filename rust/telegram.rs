@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::config::TelegramConfig;
+use crate::email::max_urgency;
+use crate::models::{Analysis, Digest};
+use crate::notifier::{Notifier, LIBRUS_URL};
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+// Telegram rejects messages longer than 4096 characters
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+static BOLD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*\*([^*]+)\*\*").unwrap());
+
+/// Telegram Bot API channel sending one compact message per student to every chat.
+pub struct TelegramClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+    chat_ids: Vec<String>,
+}
+
+impl TelegramClient {
+    pub fn from_config(config: &TelegramConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: TELEGRAM_API_URL.to_string(),
+            token: config.bot_token.clone(),
+            chat_ids: config.chat_ids.clone(),
+        }
+    }
+
+    async fn send_message(&self, chat_id: &str, text: &str) -> Result<()> {
+        let request = SendMessageRequest {
+            chat_id,
+            text,
+            parse_mode: "MarkdownV2",
+            disable_web_page_preview: true,
+        };
+        let resp = self
+            .http
+            .post(format!("{}/bot{}/sendMessage", self.base_url, self.token))
+            .json(&request)
+            .send()
+            .await
+            .context("Telegram request failed")?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("Telegram API error: {} - {}", status, body));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramClient {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn sends_each_digest(&self) -> bool {
+        true
+    }
+
+    /// Sends every digest to every chat; a failing chat does not stop the others.
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        let mut errors = Vec::new();
+        for digest in digests {
+            let text = render_message(digest);
+            let mut sent = 0;
+            for chat_id in &self.chat_ids {
+                match self.send_message(chat_id, &text).await {
+                    Ok(()) => sent += 1,
+                    Err(err) => errors.push(err),
+                }
+            }
+            tracing::info!(
+                student = %digest.student,
+                chat_count = sent,
+                "Telegram notification sent"
+            );
+        }
+        errors.into_iter().next().map_or(Ok(()), Err)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SendMessageRequest<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+    parse_mode: &'a str,
+    disable_web_page_preview: bool,
+}
+
+/// Escapes every character MarkdownV2 treats as markup.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes model output, keeping its `**bold**` spans as Telegram bold.
+fn format_markdown(text: &str) -> String {
+    let mut result = String::new();
+    let mut last = 0;
+    for caps in BOLD_RE.captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        result.push_str(&escape_markdown(&text[last..whole.start()]));
+        result.push('*');
+        result.push_str(&escape_markdown(&caps[1]));
+        result.push('*');
+        last = whole.end();
+    }
+    result.push_str(&escape_markdown(&text[last..]));
+    result
+}
+
+/// True when escaped MarkdownV2 text has an odd number of unescaped `*`.
+fn has_open_bold(text: &str) -> bool {
+    let mut open = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' => open = !open,
+            _ => {}
+        }
+    }
+    open
+}
+
+fn render_analysis(icon: &str, title: &str, analysis: &Analysis) -> String {
+    let mut section = format!(
+        "\n\n{} *{}* {} {}\n{}",
+        icon,
+        escape_markdown(title),
+        analysis.urgency.icon(),
+        analysis.urgency.label(),
+        format_markdown(&analysis.summary)
+    );
+    for point in &analysis.key_points {
+        section.push_str(&format!("\n• {}", format_markdown(point)));
+    }
+    section
+}
+
+fn render_message(digest: &Digest) -> String {
+    let urgency = max_urgency(&[digest]);
+    let mut text = format!("{} *{}*", urgency.icon(), escape_markdown(&digest.student));
+
+    let reminders = &digest.reminders;
+    if !reminders.is_empty() {
+        text.push_str(&format!(
+            "\n\n⏰ *{}* \\({}\\)",
+            escape_markdown(&reminders.label),
+            escape_markdown(&reminders.due)
+        ));
+        for item in &reminders.homework {
+            text.push_str(&format!(
+                "\n• 📝 {}: {}",
+                escape_markdown(&item.subject),
+                escape_markdown(&item.title)
+            ));
+        }
+        for item in &reminders.events {
+            text.push_str(&format!("\n• 📅 {}", escape_markdown(&item.title)));
+        }
+    }
+
+    let analyses = [
+        ("📢", "Ogłoszenia", &digest.announcements_analysis),
+        ("✉️", "Wiadomości", &digest.messages_analysis),
+        ("⭐", "Nowe oceny", &digest.grades_analysis),
+        ("📝", "Zadania domowe", &digest.homework_analysis),
+        ("📅", "Wydarzenia", &digest.events_analysis),
+    ];
+    for (icon, title, analysis) in analyses {
+        if let Some(analysis) = analysis {
+            text.push_str(&render_analysis(icon, title, analysis));
+        }
+    }
+
+    if !digest.grade_changes.is_empty() {
+        text.push_str("\n\n✏️ *Zmienione oceny*");
+        for change in &digest.grade_changes {
+            text.push_str(&format!(
+                "\n• {}: {}",
+                escape_markdown(&change.grade.subject),
+                escape_markdown(&change.details())
+            ));
+        }
+    }
+    if !digest.attendance.is_empty() {
+        text.push_str(&format!(
+            "\n\n🏫 *Frekwencja*: {} nowych wpisów do usprawiedliwienia lub spóźnień",
+            digest.attendance.len()
+        ));
+    }
+    if !digest.timetable_changes.is_empty() {
+        text.push_str("\n\n🔄 *Zmiany w planie*");
+        for change in &digest.timetable_changes {
+            text.push_str(&format!(
+                "\n• {} lekcja {}: {} \\- {}",
+                escape_markdown(&change.date),
+                escape_markdown(&change.lesson_no),
+                escape_markdown(&change.kind),
+                escape_markdown(&change.details)
+            ));
+        }
+    }
+
+    let link = format!("\n\n[Otwórz Librusa]({})", LIBRUS_URL);
+    if text.chars().count() + link.chars().count() > MAX_MESSAGE_CHARS {
+        // Room for the ellipsis and a closing `*`
+        let keep = MAX_MESSAGE_CHARS - link.chars().count() - 3;
+        let mut truncated: String = text.chars().take(keep).collect();
+        // Never leave a dangling escape backslash at the cut
+        let trailing = truncated.chars().rev().take_while(|c| *c == '\\').count();
+        if trailing % 2 == 1 {
+            truncated.pop();
+        }
+        // Telegram rejects the whole message when a bold span is left open
+        if has_open_bold(&truncated) {
+            if truncated.ends_with('*') {
+                truncated.pop();
+            } else {
+                truncated.push('*');
+            }
+        }
+        text = format!("{}\n…", truncated);
+    }
+    text.push_str(&link);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Urgency;
+
+    #[test]
+    fn test_format_markdown_escapes_and_keeps_bold() {
+        assert_eq!(
+            format_markdown("💰 **20 zł** - do 12.03!"),
+            "💰 *20 zł* \\- do 12\\.03\\!"
+        );
+    }
+
+    #[test]
+    fn test_render_message() {
+        let digest = Digest {
+            student: "Emilka • Klasa 1A".to_string(),
+            announcements_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "Wycieczka w piątek.".to_string(),
+                key_points: vec!["🎒 **piątek** - prowiant".to_string()],
+                action_items: Vec::new(),
+            }),
+            ..Digest::default()
+        };
+        let text = render_message(&digest);
+        assert!(text.starts_with("🔴 *Emilka • Klasa 1A*"));
+        assert!(text.contains("📢 *Ogłoszenia* 🔴 Pilne\nWycieczka w piątek\\."));
+        assert!(text.contains("\n• 🎒 *piątek* \\- prowiant"));
+        assert!(text.ends_with("[Otwórz Librusa](https://synergia.librus.pl/uczen/index)"));
+    }
+
+    #[test]
+    fn test_truncation_closes_bold_spans() {
+        let analysis = Analysis {
+            urgency: Urgency::Normalne,
+            summary: "Dużo ocen.".to_string(),
+            key_points: (0..200)
+                .map(|idx| format!("⭐ **bardzo dobra ocena {}** - matematyka", idx))
+                .collect(),
+            action_items: Vec::new(),
+        };
+        // Shifting the start moves the cut across the bold spans near the limit
+        for shift in 0..40 {
+            let digest = Digest {
+                student: "E".repeat(shift + 1),
+                grades_analysis: Some(analysis.clone()),
+                ..Digest::default()
+            };
+            let text = render_message(&digest);
+            assert!(text.chars().count() <= MAX_MESSAGE_CHARS, "{}", shift);
+            assert!(!has_open_bold(&text), "{}", shift);
+            assert!(!text.contains("**"), "{}", shift);
+            assert!(text.ends_with("…\n\n[Otwórz Librusa](https://synergia.librus.pl/uczen/index)"));
+        }
+        assert!(has_open_bold("*pogrub"));
+        assert!(!has_open_bold("\\*"));
+    }
+}
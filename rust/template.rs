@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use minijinja::{Environment, Value};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+static BOLD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*\*([^*]+)\*\*").unwrap());
+
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("email.html", include_str!("templates/email.html")),
+    ("email.txt", include_str!("templates/email.txt")),
+];
+
+/// Escapes text for use in HTML element content and quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes model output and turns its `**bold**` spans into `<strong>`.
+pub fn format_markdown(text: &str) -> String {
+    BOLD_RE
+        .replace_all(&escape_html(text), "<strong>$1</strong>")
+        .to_string()
+}
+
+/// Strips the `**bold**` markers the model uses, for plain text.
+pub fn plain_markdown(text: &str) -> String {
+    BOLD_RE.replace_all(text, "$1").to_string()
+}
+
+fn format_date(date_str: &str) -> String {
+    if let Some(date_part) = date_str.split('T').next() {
+        date_part.to_string()
+    } else {
+        date_str.to_string()
+    }
+}
+
+/// Builds the template environment.
+///
+/// Templates found in `override_dir` replace the embedded defaults of the same
+/// name. `.html` templates are auto-escaped; the `markdown` filter returns
+/// already-escaped HTML with `**bold**` rendered, `plain` strips the markers and
+/// `date` drops the time part of Librus timestamps.
+fn environment(override_dir: Option<&Path>) -> Result<Environment<'static>> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.add_filter("markdown", |text: &str| {
+        Value::from_safe_string(format_markdown(text))
+    });
+    env.add_filter("plain", |text: &str| plain_markdown(text));
+    env.add_filter("date", |text: &str| format_date(text));
+
+    for (name, default) in DEFAULT_TEMPLATES {
+        let source = match override_dir.map(|dir| dir.join(name)) {
+            Some(path) if path.exists() => std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read template {}", path.display()))?,
+            _ => default.to_string(),
+        };
+        env.add_template_owned(*name, source)
+            .with_context(|| format!("Invalid template {}", name))?;
+    }
+    Ok(env)
+}
+
+/// Renders `name`, preferring a template of the same name in `override_dir`.
+pub fn render<S: Serialize>(override_dir: Option<&Path>, name: &str, context: S) -> Result<String> {
+    let env = environment(override_dir)?;
+    let template = env.get_template(name)?;
+    template
+        .render(context)
+        .with_context(|| format!("Failed to render template {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_html_keeps_polish_text() {
+        assert_eq!(
+            escape_html("Zażółć gęślą jaźń — „cytat”"),
+            "Zażółć gęślą jaźń — „cytat”"
+        );
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_format_markdown_escapes_before_bold() {
+        assert_eq!(
+            format_markdown("💰 **20 zł** do <b>piątku</b>"),
+            "💰 <strong>20 zł</strong> do &lt;b&gt;piątku&lt;/b&gt;"
+        );
+        assert_eq!(
+            format_markdown("**<script>alert(1)</script>**"),
+            "<strong>&lt;script&gt;alert(1)&lt;/script&gt;</strong>"
+        );
+    }
+
+    #[test]
+    fn test_override_dir_replaces_only_matching_templates() {
+        let dir = std::env::temp_dir().join(format!("librus-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("email.txt"), "Cześć {{ name | upper }}").unwrap();
+
+        let context = minijinja::context! { name => "Emilka" };
+        let text = render(Some(&dir), "email.txt", &context).unwrap();
+        let html = render(Some(&dir), "email.html", &context).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(text, "Cześć EMILKA");
+        assert!(html.contains("Powiadomienia z Librusa"));
+    }
+}
@@ -72,6 +72,25 @@ pub fn is_within_days(date_str: &str, days: i64) -> bool {
     }
 }
 
+/// Stable FNV-1a hash of the given fields, used to notice edits of stored items.
+///
+/// `DefaultHasher` is not guaranteed to be stable between Rust releases, so it
+/// cannot be persisted.
+pub fn content_hash(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (idx, part) in parts.iter().enumerate() {
+        if idx > 0 {
+            hash ^= 0x1f;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        for byte in part.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +113,12 @@ mod tests {
         let input = "New message\n-----\nOld quoted message";
         assert_eq!(clean_message_body(input), "New message");
     }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(&[]), "cbf29ce484222325");
+        assert_eq!(content_hash(&["5", "12"]), content_hash(&["5", "12"]));
+        assert_ne!(content_hash(&["5", "12"]), content_hash(&["51", "2"]));
+        assert_ne!(content_hash(&["+/-"]), content_hash(&["+"]));
+    }
 }
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use crate::models::{TimetableChange, TimetableLesson};
+
+pub const CANCELED: &str = "Odwołana";
+pub const SUBSTITUTION: &str = "Zastępstwo";
+pub const MOVED: &str = "Przeniesiona";
+pub const ADDED: &str = "Dodatkowa";
+
+fn describe(lesson: &TimetableLesson) -> String {
+    if lesson.teacher.is_empty() {
+        lesson.subject.clone()
+    } else {
+        format!("{} ({})", lesson.subject, lesson.teacher)
+    }
+}
+
+fn change(lesson: &TimetableLesson, kind: &str, details: String) -> TimetableChange {
+    TimetableChange {
+        date: lesson.date.clone(),
+        lesson_no: lesson.lesson_no.clone(),
+        hour_from: lesson.hour_from.clone(),
+        kind: kind.to_string(),
+        subject: lesson.subject.clone(),
+        details,
+    }
+}
+
+/// Compares the freshly fetched plan for `dates` with the stored snapshot.
+///
+/// Days without a snapshot have no baseline, so only lessons Librus itself flags
+/// as cancelled or substituted are reported for them.
+pub fn diff_timetable(
+    previous: &[TimetableLesson],
+    current: &[TimetableLesson],
+    dates: &[String],
+) -> Vec<TimetableChange> {
+    let mut changes = Vec::new();
+
+    for date in dates {
+        let before: Vec<_> = previous.iter().filter(|l| &l.date == date).collect();
+        let after: Vec<_> = current.iter().filter(|l| &l.date == date).collect();
+        let has_snapshot = !before.is_empty();
+
+        for lesson in &after {
+            let slot: Vec<_> = before
+                .iter()
+                .filter(|l| l.lesson_no == lesson.lesson_no)
+                .collect();
+            let prev = slot
+                .iter()
+                .find(|l| l.subject == lesson.subject)
+                .or_else(|| slot.first());
+
+            match prev {
+                _ if !has_snapshot => {
+                    if lesson.canceled {
+                        changes.push(change(lesson, CANCELED, describe(lesson)));
+                    } else if lesson.substitution {
+                        changes.push(change(lesson, SUBSTITUTION, describe(lesson)));
+                    }
+                }
+                None => {
+                    if !lesson.canceled {
+                        let kind = if lesson.substitution { MOVED } else { ADDED };
+                        changes.push(change(lesson, kind, describe(lesson)));
+                    }
+                }
+                Some(prev) => {
+                    if lesson.canceled {
+                        if !prev.canceled {
+                            changes.push(change(lesson, CANCELED, describe(prev)));
+                        }
+                    } else if prev.subject != lesson.subject || prev.teacher != lesson.teacher {
+                        changes.push(change(
+                            lesson,
+                            SUBSTITUTION,
+                            format!("{} → {}", describe(prev), describe(lesson)),
+                        ));
+                    } else if lesson.substitution && !prev.substitution {
+                        changes.push(change(lesson, SUBSTITUTION, describe(lesson)));
+                    }
+                }
+            }
+        }
+
+        if has_snapshot {
+            let current_slots: HashSet<_> = after.iter().map(|l| &l.lesson_no).collect();
+            for lesson in before
+                .iter()
+                .filter(|l| !l.canceled && !current_slots.contains(&l.lesson_no))
+            {
+                changes.push(change(
+                    lesson,
+                    MOVED,
+                    format!("{} - zniknęła z planu", describe(lesson)),
+                ));
+            }
+        }
+    }
+
+    changes.sort_by(|a, b| {
+        (&a.date, a.lesson_no.parse::<u32>().unwrap_or(0))
+            .cmp(&(&b.date, b.lesson_no.parse::<u32>().unwrap_or(0)))
+    });
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lesson(date: &str, no: &str, subject: &str, teacher: &str) -> TimetableLesson {
+        TimetableLesson {
+            date: date.to_string(),
+            lesson_no: no.to_string(),
+            hour_from: "08:00".to_string(),
+            hour_to: "08:45".to_string(),
+            subject: subject.to_string(),
+            teacher: teacher.to_string(),
+            canceled: false,
+            substitution: false,
+        }
+    }
+
+    fn dates() -> Vec<String> {
+        vec!["2025-03-10".to_string()]
+    }
+
+    #[test]
+    fn test_unchanged_plan_has_no_changes() {
+        let plan = vec![lesson("2025-03-10", "1", "Matematyka", "Jan Kowalski")];
+        assert!(diff_timetable(&plan, &plan, &dates()).is_empty());
+    }
+
+    #[test]
+    fn test_detects_cancelled_lesson() {
+        let before = vec![lesson("2025-03-10", "1", "Matematyka", "Jan Kowalski")];
+        let mut after = before.clone();
+        after[0].canceled = true;
+
+        let changes = diff_timetable(&before, &after, &dates());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, CANCELED);
+    }
+
+    #[test]
+    fn test_detects_teacher_substitution() {
+        let before = vec![lesson("2025-03-10", "2", "Plastyka", "Anna Nowak")];
+        let after = vec![lesson("2025-03-10", "2", "Plastyka", "Ewa Zielińska")];
+
+        let changes = diff_timetable(&before, &after, &dates());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, SUBSTITUTION);
+        assert_eq!(
+            changes[0].details,
+            "Plastyka (Anna Nowak) → Plastyka (Ewa Zielińska)"
+        );
+    }
+
+    #[test]
+    fn test_detects_moved_lesson() {
+        let before = vec![
+            lesson("2025-03-10", "1", "Matematyka", "Jan Kowalski"),
+            lesson("2025-03-10", "2", "Muzyka", "Anna Nowak"),
+        ];
+        let mut moved = lesson("2025-03-10", "5", "Muzyka", "Anna Nowak");
+        moved.substitution = true;
+        let after = vec![before[0].clone(), moved];
+
+        let changes = diff_timetable(&before, &after, &dates());
+        let kinds: Vec<_> = changes.iter().map(|c| c.kind.as_str()).collect();
+        assert_eq!(kinds, vec![MOVED, MOVED]);
+        assert_eq!(changes[0].lesson_no, "2");
+        assert_eq!(changes[1].lesson_no, "5");
+    }
+
+    #[test]
+    fn test_without_snapshot_reports_only_flagged_lessons() {
+        let mut canceled = lesson("2025-03-10", "3", "WF", "Piotr Wiśniewski");
+        canceled.canceled = true;
+        let after = vec![
+            lesson("2025-03-10", "1", "Matematyka", "Jan Kowalski"),
+            canceled,
+        ];
+
+        let changes = diff_timetable(&[], &after, &dates());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, CANCELED);
+        assert_eq!(changes[0].subject, "WF");
+    }
+}
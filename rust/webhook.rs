@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::config::{ChatWebhookConfig, WebhookConfig};
+use crate::email::max_urgency;
+use crate::models::{Digest, Urgency};
+use crate::notifier::{render_markdown, truncate_chars, Notifier, LIBRUS_URL};
+
+// Discord rejects messages over 2000 characters; Slack truncates around 40000
+const DISCORD_MAX_CHARS: usize = 2000;
+const SLACK_MAX_CHARS: usize = 39000;
+
+/// Posts the structured digests as JSON to `WEBHOOK_URL`.
+pub struct WebhookNotifier {
+    http: reqwest::Client,
+    url: String,
+    bearer_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    generated_at: String,
+    urgency: Urgency,
+    digests: &'a [&'a Digest],
+}
+
+impl WebhookNotifier {
+    pub fn from_config(config: &WebhookConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: config.url.clone(),
+            bearer_token: config.bearer_token.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn is_push(&self) -> bool {
+        false
+    }
+
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        let payload = WebhookPayload {
+            generated_at: Utc::now().to_rfc3339(),
+            urgency: max_urgency(digests),
+            digests,
+        };
+        let mut builder = self.http.post(&self.url).json(&payload);
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let resp = builder.send().await.context("Webhook request failed")?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("Webhook error: {} - {}", status, body));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChatKind {
+    Discord,
+    Slack,
+}
+
+/// Discord or Slack incoming webhook, one Markdown message per student.
+pub struct ChatWebhookNotifier {
+    http: reqwest::Client,
+    kind: ChatKind,
+    url: String,
+}
+
+impl ChatWebhookNotifier {
+    pub fn discord(config: &ChatWebhookConfig) -> Self {
+        Self::new(ChatKind::Discord, config)
+    }
+
+    pub fn slack(config: &ChatWebhookConfig) -> Self {
+        Self::new(ChatKind::Slack, config)
+    }
+
+    fn new(kind: ChatKind, config: &ChatWebhookConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            kind,
+            url: config.webhook_url.clone(),
+        }
+    }
+
+    fn payload(&self, digest: &Digest) -> serde_json::Value {
+        let text = render_markdown(digest);
+        match self.kind {
+            // Messages quote teachers and parents, so @everyone must not ping anyone
+            ChatKind::Discord => serde_json::json!({
+                "content": truncate_chars(&text, DISCORD_MAX_CHARS),
+                "allowed_mentions": { "parse": [] },
+            }),
+            // Slack mrkdwn uses single asterisks for bold and <url|text> links; escaping
+            // keeps <!channel> or disguised links in messages as plain text
+            ChatKind::Slack => {
+                let text = text
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace("**", "*")
+                    .replace(
+                        &format!("[Otwórz Librusa]({})", LIBRUS_URL),
+                        &format!("<{}|Otwórz Librusa>", LIBRUS_URL),
+                    );
+                serde_json::json!({ "text": truncate_chars(&text, SLACK_MAX_CHARS) })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for ChatWebhookNotifier {
+    fn name(&self) -> &str {
+        match self.kind {
+            ChatKind::Discord => "discord",
+            ChatKind::Slack => "slack",
+        }
+    }
+
+    fn sends_each_digest(&self) -> bool {
+        true
+    }
+
+    async fn notify(&self, digests: &[&Digest]) -> Result<()> {
+        for digest in digests {
+            let resp = self
+                .http
+                .post(&self.url)
+                .json(&self.payload(digest))
+                .send()
+                .await
+                .with_context(|| format!("{} webhook request failed", self.name()))?;
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(anyhow!(
+                    "{} webhook error: {} - {}",
+                    self.name(),
+                    status,
+                    body
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_support::stub_server;
+    use crate::models::{Analysis, Urgency};
+
+    fn digest() -> Digest {
+        Digest {
+            student: "Jaś".to_string(),
+            ..Digest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_structured_digest() {
+        let (base_url, request) = stub_server("{}".to_string()).await;
+        let notifier = WebhookNotifier {
+            http: reqwest::Client::new(),
+            url: format!("{}/hook", base_url),
+            bearer_token: Some("secret".to_string()),
+        };
+        let digest = digest();
+        notifier.notify(&[&digest]).await.unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /hook"));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+        assert!(request.contains("\"student\":\"Jaś\""));
+        assert!(request.contains("\"urgency\":\"NIEPILNE\""));
+    }
+
+    #[test]
+    fn test_slack_payload_uses_mrkdwn() {
+        let notifier = ChatWebhookNotifier {
+            http: reqwest::Client::new(),
+            kind: ChatKind::Slack,
+            url: String::new(),
+        };
+        let text = notifier.payload(&digest())["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(text.starts_with("*Jaś* [NIEPILNE]"));
+        assert!(text.ends_with("|Otwórz Librusa>"));
+    }
+
+    #[test]
+    fn test_chat_payloads_do_not_ping_or_link() {
+        let hostile = Digest {
+            messages_analysis: Some(Analysis {
+                urgency: Urgency::Pilne,
+                summary: "<!channel> @everyone <https://zly.example|Librus> & co".to_string(),
+                key_points: Vec::new(),
+                action_items: Vec::new(),
+            }),
+            ..digest()
+        };
+        let chat = |kind| ChatWebhookNotifier {
+            http: reqwest::Client::new(),
+            kind,
+            url: String::new(),
+        };
+
+        let slack = chat(ChatKind::Slack).payload(&hostile)["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(slack
+            .contains("&lt;!channel&gt; @everyone &lt;https://zly.example|Librus&gt; &amp; co"));
+        assert!(slack.ends_with(&format!("<{}|Otwórz Librusa>", LIBRUS_URL)));
+
+        let discord = chat(ChatKind::Discord).payload(&hostile);
+        assert_eq!(discord["allowed_mentions"]["parse"], serde_json::json!([]));
+    }
+}